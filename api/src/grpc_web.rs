@@ -0,0 +1,471 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use bytes::{BufMut, Bytes, BytesMut};
+use http_body::Body as _;
+use hyper::header::{self, HeaderMap, HeaderName, HeaderValue};
+use hyper::{Body, Method, Request, Response};
+use serde::Deserialize;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{ready, Context, Poll};
+use std::time::Duration;
+use terrarium_core::reload::Live;
+use tonic::body::BoxBody;
+use tower::{Layer, Service};
+use tower_http::cors::{AllowOrigin, CorsLayer};
+
+/// Browser access to the gRPC server. Applied on config reload.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GrpcWebSettings {
+    /// Origins such as `https://app.example.com` whose pages may call the API
+    /// cross-origin; `*` allows any. Same-origin pages need no entry.
+    pub allowed_origins: Vec<String>,
+}
+
+impl GrpcWebSettings {
+    pub fn validate(&self) -> Result<(), Box<dyn std::error::Error>> {
+        for origin in &self.allowed_origins {
+            let valid = origin == "*"
+                || ((origin.starts_with("http://") || origin.starts_with("https://"))
+                    && !origin.ends_with('/'));
+            if !valid {
+                return Err(format!(
+                    "grpc_web.allowed_origins: {:?} must be \"*\" or scheme://host[:port]",
+                    origin
+                )
+                .into());
+            }
+        }
+        Ok(())
+    }
+
+    fn allows(&self, origin: &HeaderValue) -> bool {
+        self.allowed_origins
+            .iter()
+            .any(|allowed| allowed == "*" || allowed.as_bytes() == origin.as_bytes())
+    }
+}
+
+/// Headers grpc-web clients send, allowed on preflight requests.
+const ALLOW_HEADERS: [&str; 5] = [
+    "content-type",
+    "x-grpc-web",
+    "x-user-agent",
+    "grpc-timeout",
+    "authorization",
+];
+/// Status headers grpc-web clients read from responses.
+const EXPOSE_HEADERS: [&str; 3] = ["grpc-status", "grpc-message", "grpc-status-details-bin"];
+/// Flag marking the frame that carries the trailers in a grpc-web body.
+const TRAILERS_FLAG: u8 = 0x80;
+
+/// Answers CORS preflights and labels responses for the origins in
+/// `allowed_origins`, re-read on every request so reloads apply.
+pub fn cors(settings: Arc<Live<GrpcWebSettings>>) -> CorsLayer {
+    CorsLayer::new()
+        .allow_origin(AllowOrigin::predicate(move |origin, _| {
+            settings.get().allows(origin)
+        }))
+        .allow_methods([Method::POST])
+        .allow_headers(ALLOW_HEADERS.map(HeaderName::from_static))
+        .expose_headers(EXPOSE_HEADERS.map(HeaderName::from_static))
+        .max_age(Duration::from_secs(86400))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Encoding {
+    Binary,
+    /// `application/grpc-web-text`: the body is base64 encoded.
+    Text,
+}
+
+impl Encoding {
+    fn of(headers: &HeaderMap) -> Option<Self> {
+        let content_type = headers.get(header::CONTENT_TYPE)?.to_str().ok()?;
+        if content_type.starts_with("application/grpc-web-text") {
+            Some(Self::Text)
+        } else if content_type.starts_with("application/grpc-web") {
+            Some(Self::Binary)
+        } else {
+            None
+        }
+    }
+
+    fn content_type(self) -> &'static str {
+        match self {
+            Self::Binary => "application/grpc-web+proto",
+            Self::Text => "application/grpc-web-text+proto",
+        }
+    }
+}
+
+/// Translates gRPC-Web requests into gRPC for the services behind it, over
+/// HTTP/1.1 or HTTP/2. Bodies are streamed in both directions; plain gRPC
+/// passes through. CORS is left to [`cors`].
+#[derive(Clone, Default)]
+pub struct GrpcWebLayer;
+
+impl<S> Layer<S> for GrpcWebLayer {
+    type Service = GrpcWeb<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        GrpcWeb { inner }
+    }
+}
+
+#[derive(Clone)]
+pub struct GrpcWeb<S> {
+    inner: S,
+}
+
+type ResponseFuture<E> = Pin<Box<dyn Future<Output = Result<Response<BoxBody>, E>> + Send>>;
+
+impl<S> Service<Request<Body>> for GrpcWeb<S>
+where
+    S: Service<Request<Body>, Response = Response<BoxBody>> + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response<BoxBody>;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Error>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        let Some(encoding) = Encoding::of(req.headers()) else {
+            return Box::pin(self.inner.call(req));
+        };
+        let call = self.inner.call(to_grpc_request(req, encoding));
+        Box::pin(async move {
+            let resp = call.await?;
+            Ok(to_grpc_web_response(resp, encoding))
+        })
+    }
+}
+
+fn to_grpc_request(req: Request<Body>, encoding: Encoding) -> Request<Body> {
+    let (mut parts, body) = req.into_parts();
+    parts.headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/grpc"),
+    );
+    parts.headers.remove(header::CONTENT_LENGTH);
+    let body = match encoding {
+        Encoding::Binary => body,
+        Encoding::Text => decode_text(body),
+    };
+    Request::from_parts(parts, body)
+}
+
+/// Decodes a base64 request body as it arrives. A chunk may end mid-quantum,
+/// so up to three characters are carried over to the next one.
+fn decode_text(mut body: Body) -> Body {
+    let (mut sender, decoded) = Body::channel();
+    tokio::spawn(async move {
+        let mut pending = BytesMut::new();
+        while let Some(chunk) = body.data().await {
+            let Ok(chunk) = chunk else {
+                return sender.abort();
+            };
+            pending.extend_from_slice(&chunk);
+            let whole = pending.len() - pending.len() % 4;
+            let quanta = pending.split_to(whole);
+            match STANDARD.decode(&quanta) {
+                Ok(data) if data.is_empty() => {}
+                Ok(data) => {
+                    if sender.send_data(data.into()).await.is_err() {
+                        return;
+                    }
+                }
+                Err(_) => return sender.abort(),
+            }
+        }
+        if !pending.is_empty() {
+            sender.abort();
+        }
+    });
+    decoded
+}
+
+/// A grpc-web trailers frame: the flag, a big-endian length and the trailers
+/// as HTTP/1 header lines.
+fn trailers_frame(trailers: &HeaderMap) -> Bytes {
+    let mut block = Vec::new();
+    for (name, value) in trailers {
+        block.extend_from_slice(name.as_str().as_bytes());
+        block.extend_from_slice(b": ");
+        block.extend_from_slice(value.as_bytes());
+        block.extend_from_slice(b"\r\n");
+    }
+    let mut frame = BytesMut::with_capacity(5 + block.len());
+    frame.put_u8(TRAILERS_FLAG);
+    frame.put_u32(block.len() as u32);
+    frame.put_slice(&block);
+    frame.freeze()
+}
+
+fn to_grpc_web_response(resp: Response<BoxBody>, encoding: Encoding) -> Response<BoxBody> {
+    let (mut parts, body) = resp.into_parts();
+    parts.headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static(encoding.content_type()),
+    );
+    parts.headers.remove(header::CONTENT_LENGTH);
+    let body = GrpcWebBody {
+        inner: body,
+        encoding,
+        unencoded: BytesMut::new(),
+        data_done: false,
+        done: false,
+    };
+    Response::from_parts(parts, body.boxed_unsync())
+}
+
+/// A gRPC response body re-framed for grpc-web: data passes through as it
+/// arrives and the trailers follow as a final frame.
+struct GrpcWebBody {
+    inner: BoxBody,
+    encoding: Encoding,
+    /// Text responses only: up to two bytes waiting for a whole base64 quantum.
+    unencoded: BytesMut,
+    data_done: bool,
+    done: bool,
+}
+
+impl GrpcWebBody {
+    fn encode(&mut self, data: Bytes, last: bool) -> Bytes {
+        match self.encoding {
+            Encoding::Binary => data,
+            Encoding::Text => {
+                self.unencoded.extend_from_slice(&data);
+                let whole = if last {
+                    self.unencoded.len()
+                } else {
+                    self.unencoded.len() - self.unencoded.len() % 3
+                };
+                STANDARD.encode(self.unencoded.split_to(whole)).into()
+            }
+        }
+    }
+}
+
+impl http_body::Body for GrpcWebBody {
+    type Data = Bytes;
+    type Error = tonic::Status;
+
+    fn poll_data(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Bytes, tonic::Status>>> {
+        if self.done {
+            return Poll::Ready(None);
+        }
+        while !self.data_done {
+            match ready!(Pin::new(&mut self.inner).poll_data(cx)) {
+                Some(Ok(data)) => {
+                    let data = self.encode(data, false);
+                    if !data.is_empty() {
+                        return Poll::Ready(Some(Ok(data)));
+                    }
+                }
+                Some(Err(status)) => return Poll::Ready(Some(Err(status))),
+                None => self.data_done = true,
+            }
+        }
+        let trailers = match ready!(Pin::new(&mut self.inner).poll_trailers(cx)) {
+            Ok(trailers) => trailers,
+            Err(status) => return Poll::Ready(Some(Err(status))),
+        };
+        self.done = true;
+        let frame = trailers.as_ref().map(trailers_frame).unwrap_or_default();
+        Poll::Ready(Some(Ok(self.encode(frame, true))))
+    }
+
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, tonic::Status>> {
+        Poll::Ready(Ok(None))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.done
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::Infallible;
+
+    /// A gRPC service that replies with the request body and an OK status.
+    async fn echo(req: Request<Body>) -> Result<Response<BoxBody>, Infallible> {
+        assert_eq!(req.headers()[header::CONTENT_TYPE], "application/grpc");
+        let body = hyper::body::to_bytes(req.into_body()).await.unwrap();
+        let (mut sender, reply) = Body::channel();
+        tokio::spawn(async move {
+            sender.send_data(body).await.unwrap();
+            let mut trailers = HeaderMap::new();
+            trailers.insert("grpc-status", HeaderValue::from_static("0"));
+            sender.send_trailers(trailers).await.unwrap();
+        });
+        let reply = reply
+            .map_err(|e| tonic::Status::from_error(Box::new(e)))
+            .boxed_unsync();
+        let mut resp = Response::new(reply);
+        resp.headers_mut().insert(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("application/grpc"),
+        );
+        Ok(resp)
+    }
+
+    fn service(
+        settings: Arc<Live<GrpcWebSettings>>,
+    ) -> impl Service<Request<Body>, Response = Response<BoxBody>, Error = Infallible> {
+        tower::ServiceBuilder::new()
+            .layer(cors(settings))
+            .layer(GrpcWebLayer)
+            .service_fn(echo)
+    }
+
+    fn allowing(origin: &str) -> Arc<Live<GrpcWebSettings>> {
+        Live::new(GrpcWebSettings {
+            allowed_origins: vec![origin.to_string()],
+        })
+    }
+
+    fn request(method: Method, content_type: &str, origin: &str, body: Body) -> Request<Body> {
+        Request::builder()
+            .method(method)
+            .uri("/hello.HelloApi/SayHello")
+            .header(header::CONTENT_TYPE, content_type)
+            .header(header::ORIGIN, origin)
+            .header(header::ACCESS_CONTROL_REQUEST_METHOD, "POST")
+            .body(body)
+            .unwrap()
+    }
+
+    async fn body_bytes(resp: Response<BoxBody>) -> Bytes {
+        let mut body = resp.into_body();
+        let mut buffer = BytesMut::new();
+        while let Some(chunk) = body.data().await {
+            buffer.put(chunk.unwrap());
+        }
+        buffer.freeze()
+    }
+
+    #[test]
+    fn encodes_trailers_frame() {
+        let mut trailers = HeaderMap::new();
+        trailers.insert("grpc-status", HeaderValue::from_static("0"));
+        assert_eq!(
+            &trailers_frame(&trailers)[..],
+            b"\x80\x00\x00\x00\x10grpc-status: 0\r\n"
+        );
+    }
+
+    #[test]
+    fn validates_allowed_origins() {
+        let settings = |origin: &str| GrpcWebSettings {
+            allowed_origins: vec![origin.to_string()],
+        };
+        settings("*").validate().unwrap();
+        settings("http://localhost:3000").validate().unwrap();
+        assert!(settings("localhost:3000").validate().is_err());
+        assert!(settings("https://app.example.com/").validate().is_err());
+    }
+
+    #[tokio::test]
+    async fn answers_preflights_for_allowed_origins() {
+        let settings = allowing("https://app.example.com");
+        let mut service = service(settings.clone());
+        let preflight = |origin| request(Method::OPTIONS, "", origin, Body::empty());
+        let resp = service
+            .call(preflight("https://app.example.com"))
+            .await
+            .unwrap();
+        assert_eq!(
+            resp.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+            "https://app.example.com"
+        );
+        assert!(resp.headers()[header::ACCESS_CONTROL_ALLOW_HEADERS]
+            .to_str()
+            .unwrap()
+            .contains("x-grpc-web"));
+
+        let resp = service
+            .call(preflight("https://evil.example.com"))
+            .await
+            .unwrap();
+        assert!(!resp
+            .headers()
+            .contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN));
+
+        // Reloaded origins apply to the next request.
+        settings.set(GrpcWebSettings {
+            allowed_origins: vec!["*".to_string()],
+        });
+        let resp = service
+            .call(preflight("https://evil.example.com"))
+            .await
+            .unwrap();
+        assert_eq!(
+            resp.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+            "https://evil.example.com"
+        );
+    }
+
+    #[tokio::test]
+    async fn translates_binary_and_text_calls() {
+        let frame = b"\x00\x00\x00\x00\x02hi";
+        let resp = service(allowing("https://app.example.com"))
+            .call(request(
+                Method::POST,
+                "application/grpc-web+proto",
+                "https://app.example.com",
+                Body::from(frame.to_vec()),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(
+            resp.headers()[header::CONTENT_TYPE],
+            "application/grpc-web+proto"
+        );
+        assert!(resp.headers()[header::ACCESS_CONTROL_EXPOSE_HEADERS]
+            .to_str()
+            .unwrap()
+            .contains("grpc-status"));
+        let body = body_bytes(resp).await;
+        assert_eq!(&body[..7], frame);
+        assert_eq!(&body[7..], b"\x80\x00\x00\x00\x10grpc-status: 0\r\n");
+
+        // The base64 request arrives split mid-quantum.
+        let encoded = STANDARD.encode(frame);
+        let (mut sender, body) = Body::channel();
+        tokio::spawn(async move {
+            for piece in encoded.as_bytes().chunks(3) {
+                sender
+                    .send_data(Bytes::copy_from_slice(piece))
+                    .await
+                    .unwrap();
+            }
+        });
+        let resp = service(allowing("https://app.example.com"))
+            .call(request(
+                Method::POST,
+                "application/grpc-web-text",
+                "https://app.example.com",
+                body,
+            ))
+            .await
+            .unwrap();
+        let body = STANDARD.decode(body_bytes(resp).await).unwrap();
+        assert_eq!(&body[..7], frame);
+        assert_eq!(&body[7..], b"\x80\x00\x00\x00\x10grpc-status: 0\r\n");
+    }
+}
@@ -0,0 +1,287 @@
+use crate::KafkaService;
+use log::{error, info, warn};
+use prometheus::{IntCounter, IntGauge, Registry};
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Postgres};
+use std::sync::Arc;
+use std::time::Duration;
+use terrarium_core::metrics;
+use terrarium_core::reload::Live;
+use tokio::time::sleep;
+use uuid::Uuid;
+
+/// Transactional outbox settings. When enabled, `SayHello` writes events to the
+/// `outbox` table and a background relay publishes them to Kafka.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OutboxSettings {
+    pub enabled: bool,
+    pub poll_interval_ms: u64,
+    pub batch_size: i64,
+    pub base_retry_delay_ms: u64,
+    pub max_retry_delay_ms: u64,
+    /// How long a claimed batch stays reserved for this relay. Rows still
+    /// unsent when it expires, e.g. after a crash, are claimed again.
+    pub claim_timeout_ms: u64,
+}
+
+impl Default for OutboxSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            poll_interval_ms: 500,
+            batch_size: 100,
+            base_retry_delay_ms: 500,
+            max_retry_delay_ms: 30_000,
+            claim_timeout_ms: 300_000,
+        }
+    }
+}
+
+impl OutboxSettings {
+    pub fn validate(&self) -> Result<(), Box<dyn std::error::Error>> {
+        if self.batch_size <= 0 {
+            return Err("outbox.batch_size must be greater than 0".into());
+        }
+        if self.poll_interval_ms == 0 {
+            return Err("outbox.poll_interval_ms must be greater than 0".into());
+        }
+        if self.claim_timeout_ms == 0 {
+            return Err("outbox.claim_timeout_ms must be greater than 0".into());
+        }
+        if self.base_retry_delay_ms > self.max_retry_delay_ms {
+            return Err("outbox.base_retry_delay_ms must not exceed max_retry_delay_ms".into());
+        }
+        Ok(())
+    }
+
+    /// Exponential backoff after `consecutive_failures` failed relay attempts.
+    fn retry_delay(&self, consecutive_failures: u32) -> Duration {
+        let factor = 2u64.saturating_pow(consecutive_failures.saturating_sub(1));
+        let delay = self.base_retry_delay_ms.saturating_mul(factor);
+        Duration::from_millis(delay.min(self.max_retry_delay_ms))
+    }
+}
+
+pub async fn enqueue(
+    pool: &Pool<Postgres>,
+    event_id: Uuid,
+    topic: &str,
+    key: &str,
+    payload: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO outbox (event_id, topic, message_key, payload) VALUES ($1, $2, $3, $4)",
+    )
+    .bind(event_id)
+    .bind(topic)
+    .bind(key)
+    .bind(payload)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+#[derive(sqlx::FromRow)]
+struct OutboxRow {
+    id: i64,
+    event_id: Uuid,
+    topic: String,
+    message_key: Option<String>,
+    payload: String,
+}
+
+/// Background task that publishes pending outbox rows to Kafka, at least once.
+/// A single relay sends them in insertion order, but rows are not ordered
+/// across relays, nor when an expired lease lets older rows go out again after
+/// newer ones.
+pub struct OutboxRelay {
+    pool: Pool<Postgres>,
+    kafka: Arc<KafkaService>,
+    /// Re-read on every iteration so a config reload applies to the next batch.
+    settings: Arc<Live<OutboxSettings>>,
+    relayed: IntCounter,
+    relay_failures: IntCounter,
+    pending: IntGauge,
+}
+
+impl OutboxRelay {
+    pub fn new(
+        pool: Pool<Postgres>,
+        kafka: Arc<KafkaService>,
+        settings: Arc<Live<OutboxSettings>>,
+        registry: &Registry,
+    ) -> Result<Self, prometheus::Error> {
+        let relayed = metrics::register_int_counter(
+            registry,
+            "api_outbox_relayed_total",
+            "Total number of outbox events published to Kafka",
+        )?;
+        let relay_failures = metrics::register_int_counter(
+            registry,
+            "api_outbox_relay_failures_total",
+            "Total number of failed attempts to publish outbox events",
+        )?;
+        let pending = metrics::register_int_gauge(
+            registry,
+            "api_outbox_pending",
+            "Number of outbox events waiting to be published",
+        )?;
+
+        Ok(Self {
+            pool,
+            kafka,
+            settings,
+            relayed,
+            relay_failures,
+            pending,
+        })
+    }
+
+    pub async fn run(self) {
+        info!("Starting outbox relay with {:?}", self.settings.get());
+        let mut consecutive_failures = 0u32;
+
+        loop {
+            let settings = self.settings.get();
+            match self.relay_batch(&settings).await {
+                Ok(BatchOutcome::Drained(0)) => {
+                    consecutive_failures = 0;
+                    sleep(Duration::from_millis(settings.poll_interval_ms)).await;
+                }
+                Ok(BatchOutcome::Drained(_)) => consecutive_failures = 0,
+                Ok(BatchOutcome::Stalled) => {
+                    consecutive_failures = consecutive_failures.saturating_add(1);
+                    sleep(settings.retry_delay(consecutive_failures)).await;
+                }
+                Err(e) => {
+                    error!("Outbox relay database error: {}", e);
+                    consecutive_failures = consecutive_failures.saturating_add(1);
+                    sleep(settings.retry_delay(consecutive_failures)).await;
+                }
+            }
+        }
+    }
+
+    /// Publishes up to `batch_size` pending rows in id order. Stops at the first
+    /// failure and hands the rest of the batch back, so this relay retries from
+    /// that row instead of skipping ahead of it.
+    async fn relay_batch(&self, settings: &OutboxSettings) -> Result<BatchOutcome, sqlx::Error> {
+        let rows = self.claim(settings).await?;
+
+        let mut outcome = BatchOutcome::Drained(rows.len());
+        for (i, row) in rows.iter().enumerate() {
+            let key = row.message_key.as_deref().unwrap_or_default();
+            match self.kafka.send(&row.topic, key, &row.payload).await {
+                Ok((partition, offset)) => {
+                    sqlx::query(
+                        "UPDATE outbox SET sent_at = NOW(), claimed_until = NULL WHERE id = $1",
+                    )
+                    .bind(row.id)
+                    .execute(&self.pool)
+                    .await?;
+                    self.relayed.inc();
+                    info!(
+                        "Relayed outbox event {} to {}[{}]@{}",
+                        row.event_id, row.topic, partition, offset
+                    );
+                }
+                Err(e) => {
+                    sqlx::query(
+                        "UPDATE outbox SET attempts = attempts + 1, last_error = $2 WHERE id = $1",
+                    )
+                    .bind(row.id)
+                    .bind(e.message())
+                    .execute(&self.pool)
+                    .await?;
+                    self.relay_failures.inc();
+                    warn!("Failed to relay outbox event {}: {}", row.event_id, e);
+                    // Hand the rest back so the retry starts from this row.
+                    let unsent: Vec<i64> = rows[i..].iter().map(|row| row.id).collect();
+                    sqlx::query("UPDATE outbox SET claimed_until = NULL WHERE id = ANY($1)")
+                        .bind(&unsent)
+                        .execute(&self.pool)
+                        .await?;
+                    outcome = BatchOutcome::Stalled;
+                    break;
+                }
+            }
+        }
+
+        let pending: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM outbox WHERE sent_at IS NULL")
+            .fetch_one(&self.pool)
+            .await?;
+        self.pending.set(pending);
+
+        Ok(outcome)
+    }
+
+    /// Leases the oldest unclaimed pending rows in a short transaction, so no
+    /// locks or connections are held while they are published.
+    async fn claim(&self, settings: &OutboxSettings) -> Result<Vec<OutboxRow>, sqlx::Error> {
+        let mut rows = sqlx::query_as::<_, OutboxRow>(
+            "UPDATE outbox \
+             SET claimed_until = NOW() + $2 * INTERVAL '1 millisecond' \
+             WHERE id IN ( \
+                 SELECT id FROM outbox \
+                 WHERE sent_at IS NULL AND (claimed_until IS NULL OR claimed_until < NOW()) \
+                 ORDER BY id \
+                 LIMIT $1 \
+                 FOR UPDATE SKIP LOCKED \
+             ) \
+             RETURNING id, event_id, topic, message_key, payload",
+        )
+        .bind(settings.batch_size)
+        .bind(settings.claim_timeout_ms as f64)
+        .fetch_all(&self.pool)
+        .await?;
+        // RETURNING doesn't preserve the subquery's order.
+        rows.sort_by_key(|row| row.id);
+        Ok(rows)
+    }
+}
+
+enum BatchOutcome {
+    Drained(usize),
+    Stalled,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defaults_are_disabled_and_valid() {
+        let settings: OutboxSettings = serde_json::from_str("{}").unwrap();
+        assert!(!settings.enabled);
+        assert!(settings.validate().is_ok());
+    }
+
+    #[test]
+    fn rejects_invalid_settings() {
+        let settings: OutboxSettings = serde_json::from_str(r#"{"batch_size": 0}"#).unwrap();
+        assert!(settings.validate().is_err());
+
+        let settings: OutboxSettings = serde_json::from_str(r#"{"claim_timeout_ms": 0}"#).unwrap();
+        assert!(settings.validate().is_err());
+
+        let settings: OutboxSettings =
+            serde_json::from_str(r#"{"base_retry_delay_ms": 10, "max_retry_delay_ms": 5}"#)
+                .unwrap();
+        assert!(settings.validate().is_err());
+    }
+
+    #[test]
+    fn retry_delay_backs_off_exponentially_up_to_max() {
+        let settings = OutboxSettings {
+            base_retry_delay_ms: 100,
+            max_retry_delay_ms: 1_000,
+            ..OutboxSettings::default()
+        };
+        assert_eq!(settings.retry_delay(1), Duration::from_millis(100));
+        assert_eq!(settings.retry_delay(2), Duration::from_millis(200));
+        assert_eq!(settings.retry_delay(4), Duration::from_millis(800));
+        assert_eq!(settings.retry_delay(5), Duration::from_millis(1_000));
+        assert_eq!(settings.retry_delay(64), Duration::from_millis(1_000));
+    }
+}
@@ -0,0 +1,307 @@
+use rdkafka::config::ClientConfig;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use terrarium_core::kafka_security::KafkaSecurity;
+
+/// Codecs the bundled librdkafka supports. It is built without zstd (rdkafka's
+/// `zstd` feature pulls in libzstd), so `zstd` is rejected here rather than
+/// failing once the producer is created.
+const COMPRESSION_CODECS: &[&str] = &["none", "gzip", "snappy", "lz4"];
+
+/// Properties that have a first-class option below and so may not also be set
+/// through the `properties` passthrough.
+const MANAGED_PROPERTIES: &[&str] = &[
+    "bootstrap.servers",
+    "security.protocol",
+    "sasl.mechanism",
+    "sasl.username",
+    "sasl.password",
+    "ssl.ca.location",
+    "ssl.certificate.location",
+    "ssl.key.location",
+    "ssl.key.password",
+    "enable.idempotence",
+    "acks",
+    "compression.type",
+    "linger.ms",
+    "batch.size",
+    "message.timeout.ms",
+];
+
+/// librdkafka's other names for managed properties, as `(alias, property)`.
+const PROPERTY_ALIASES: &[(&str, &str)] = &[
+    ("metadata.broker.list", "bootstrap.servers"),
+    ("sasl.mechanisms", "sasl.mechanism"),
+    ("request.required.acks", "acks"),
+    ("compression.codec", "compression.type"),
+    ("queue.buffering.max.ms", "linger.ms"),
+    ("delivery.timeout.ms", "message.timeout.ms"),
+];
+
+/// The managed property `key` sets, under either of its names.
+fn managed_property(key: &str) -> Option<&'static str> {
+    let key = PROPERTY_ALIASES
+        .iter()
+        .find(|(alias, _)| *alias == key)
+        .map_or(key, |(_, property)| property);
+    MANAGED_PROPERTIES.iter().find(|p| **p == key).copied()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum Acks {
+    #[serde(rename = "0")]
+    None,
+    #[serde(rename = "1")]
+    Leader,
+    #[serde(rename = "all")]
+    All,
+}
+
+impl Acks {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Acks::None => "0",
+            Acks::Leader => "1",
+            Acks::All => "all",
+        }
+    }
+}
+
+/// Kafka producer tuning. Unset options fall back to librdkafka defaults.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProducerSettings {
+    pub enable_idempotence: Option<bool>,
+    pub acks: Option<Acks>,
+    pub compression: Option<String>,
+    pub linger_ms: Option<u64>,
+    pub batch_size: Option<u64>,
+    #[serde(default = "default_message_timeout_ms")]
+    pub message_timeout_ms: u64,
+    /// Arbitrary librdkafka properties passed through to the producer as-is.
+    #[serde(default)]
+    pub properties: BTreeMap<String, String>,
+}
+
+fn default_message_timeout_ms() -> u64 {
+    5000
+}
+
+impl Default for ProducerSettings {
+    fn default() -> Self {
+        Self {
+            enable_idempotence: None,
+            acks: None,
+            compression: None,
+            linger_ms: None,
+            batch_size: None,
+            message_timeout_ms: default_message_timeout_ms(),
+            properties: BTreeMap::new(),
+        }
+    }
+}
+
+impl ProducerSettings {
+    pub fn validate(&self) -> Result<(), Box<dyn std::error::Error>> {
+        if self.enable_idempotence == Some(true) {
+            if let Some(acks) = self.acks.filter(|a| *a != Acks::All) {
+                return Err(format!(
+                    "producer.enable_idempotence requires acks = \"all\", got \"{}\"",
+                    acks.as_str()
+                )
+                .into());
+            }
+        }
+        if let Some(codec) = &self.compression {
+            if !COMPRESSION_CODECS.contains(&codec.as_str()) {
+                return Err(format!(
+                    "producer.compression must be one of {:?}, got \"{}\"",
+                    COMPRESSION_CODECS, codec
+                )
+                .into());
+            }
+        }
+        if self.batch_size == Some(0) {
+            return Err("producer.batch_size must be greater than 0".into());
+        }
+        if self.message_timeout_ms == 0 {
+            return Err("producer.message_timeout_ms must be greater than 0".into());
+        }
+        if let Some((key, property)) = self
+            .properties
+            .keys()
+            .find_map(|k| managed_property(k).map(|p| (k, p)))
+        {
+            let alias = if key == property {
+                String::new()
+            } else {
+                format!(" (an alias of \"{}\")", property)
+            };
+            return Err(format!(
+                "producer.properties may not set \"{}\"{}; use the dedicated option or kafka_security instead",
+                key, alias
+            )
+            .into());
+        }
+        Ok(())
+    }
+
+    /// The librdkafka properties this configuration resolves to, including the broker list.
+    pub fn effective_properties(&self, kafka_broker: &str) -> BTreeMap<String, String> {
+        let mut props = self.properties.clone();
+        props.insert("bootstrap.servers".to_string(), kafka_broker.to_string());
+        props.insert(
+            "message.timeout.ms".to_string(),
+            self.message_timeout_ms.to_string(),
+        );
+        if let Some(idempotence) = self.enable_idempotence {
+            props.insert("enable.idempotence".to_string(), idempotence.to_string());
+        }
+        if let Some(acks) = self.acks {
+            props.insert("acks".to_string(), acks.as_str().to_string());
+        }
+        if let Some(codec) = &self.compression {
+            props.insert("compression.type".to_string(), codec.clone());
+        }
+        if let Some(linger) = self.linger_ms {
+            props.insert("linger.ms".to_string(), linger.to_string());
+        }
+        if let Some(batch_size) = self.batch_size {
+            props.insert("batch.size".to_string(), batch_size.to_string());
+        }
+        props
+    }
+
+    pub fn client_config(
+        &self,
+        kafka_broker: &str,
+        security: &KafkaSecurity,
+    ) -> Result<ClientConfig, Box<dyn std::error::Error>> {
+        let mut props = self.effective_properties(kafka_broker);
+        props.extend(security.properties()?);
+        log::info!("Kafka producer configuration: {}", describe(&props));
+
+        let mut client_config = ClientConfig::new();
+        for (key, value) in &props {
+            client_config.set(key, value);
+        }
+        Ok(client_config)
+    }
+}
+
+/// Renders properties for logging with anything that looks like a secret redacted.
+fn describe(props: &BTreeMap<String, String>) -> String {
+    props
+        .iter()
+        .map(|(k, v)| {
+            if k.contains("password") || k.contains("secret") {
+                format!("{}=<redacted>", k)
+            } else {
+                format!("{}={}", k, v)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(json: &str) -> ProducerSettings {
+        serde_json::from_str(json).expect("producer settings should parse")
+    }
+
+    #[test]
+    fn defaults_only_set_broker_and_timeout() {
+        let props = ProducerSettings::default().effective_properties("localhost:9092");
+        assert_eq!(props.len(), 2);
+        assert_eq!(props["bootstrap.servers"], "localhost:9092");
+        assert_eq!(props["message.timeout.ms"], "5000");
+    }
+
+    #[test]
+    fn maps_first_class_options_and_passthrough() {
+        let settings = parse(
+            r#"{
+                "enable_idempotence": true,
+                "acks": "all",
+                "compression": "lz4",
+                "linger_ms": 20,
+                "batch_size": 65536,
+                "properties": { "client.id": "terrarium-api" }
+            }"#,
+        );
+        settings.validate().unwrap();
+
+        let props = settings.effective_properties("broker:9092");
+        assert_eq!(props["enable.idempotence"], "true");
+        assert_eq!(props["acks"], "all");
+        assert_eq!(props["compression.type"], "lz4");
+        assert_eq!(props["linger.ms"], "20");
+        assert_eq!(props["batch.size"], "65536");
+        assert_eq!(props["client.id"], "terrarium-api");
+    }
+
+    #[test]
+    fn rejects_invalid_settings() {
+        assert!(parse(r#"{"enable_idempotence": true, "acks": "1"}"#)
+            .validate()
+            .is_err());
+        assert!(parse(r#"{"compression": "brotli"}"#).validate().is_err());
+        assert!(parse(r#"{"compression": "zstd"}"#).validate().is_err());
+        assert!(parse(r#"{"batch_size": 0}"#).validate().is_err());
+        for key in [
+            "acks",
+            "request.required.acks",
+            "queue.buffering.max.ms",
+            "compression.codec",
+        ] {
+            let settings = parse(&format!(r#"{{"properties": {{"{}": "1"}}}}"#, key));
+            assert!(settings.validate().is_err(), "{}", key);
+        }
+        let err = parse(r#"{"properties": {"queue.buffering.max.ms": "5"}}"#)
+            .validate()
+            .unwrap_err();
+        assert!(
+            err.to_string().contains("alias of \"linger.ms\""),
+            "{}",
+            err
+        );
+        assert!(serde_json::from_str::<ProducerSettings>(r#"{"acks": "2"}"#).is_err());
+    }
+
+    #[test]
+    fn aliases_name_managed_properties() {
+        for (alias, property) in PROPERTY_ALIASES {
+            assert_eq!(managed_property(alias), Some(*property), "{}", alias);
+        }
+        assert_eq!(managed_property("client.id"), None);
+    }
+
+    #[test]
+    fn builds_a_producer_for_every_accepted_codec() {
+        use rdkafka::producer::FutureProducer;
+
+        for codec in COMPRESSION_CODECS {
+            let settings = parse(&format!(r#"{{"compression": "{}"}}"#, codec));
+            settings.validate().unwrap();
+            let config = settings
+                .client_config("localhost:9092", &KafkaSecurity::default())
+                .unwrap();
+            if let Err(e) = config.create::<FutureProducer>() {
+                panic!("compression {} is not supported: {}", codec, e);
+            }
+        }
+    }
+
+    #[test]
+    fn redacts_secrets_when_described() {
+        let mut props = BTreeMap::new();
+        props.insert("sasl.password".to_string(), "hunter2".to_string());
+        props.insert("client.id".to_string(), "api".to_string());
+        let described = describe(&props);
+        assert!(described.contains("client.id=api"));
+        assert!(!described.contains("hunter2"));
+    }
+}
@@ -0,0 +1,140 @@
+use prometheus::{IntCounter, Registry};
+use serde::Deserialize;
+use std::sync::Mutex;
+use std::time::Instant;
+use terrarium_core::metrics;
+
+/// Token bucket limiting `SayHello` calls across all clients.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RateLimitSettings {
+    /// Sustained calls per second.
+    pub requests_per_second: f64,
+    /// Calls allowed at once after an idle period.
+    pub burst: u32,
+}
+
+impl RateLimitSettings {
+    pub fn validate(&self) -> Result<(), Box<dyn std::error::Error>> {
+        if !self.requests_per_second.is_finite() || self.requests_per_second <= 0.0 {
+            return Err("rate_limit.requests_per_second must be greater than 0".into());
+        }
+        if self.burst == 0 {
+            return Err("rate_limit.burst must be greater than 0".into());
+        }
+        Ok(())
+    }
+}
+
+struct Bucket {
+    settings: Option<RateLimitSettings>,
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+pub struct RateLimiter {
+    bucket: Mutex<Bucket>,
+    limited: IntCounter,
+}
+
+impl RateLimiter {
+    /// `None` lets every call through.
+    pub fn new(
+        settings: Option<RateLimitSettings>,
+        registry: &Registry,
+    ) -> Result<Self, prometheus::Error> {
+        Ok(Self {
+            bucket: Mutex::new(Bucket {
+                tokens: settings.as_ref().map_or(0.0, |s| f64::from(s.burst)),
+                settings,
+                refilled_at: Instant::now(),
+            }),
+            limited: metrics::register_int_counter(
+                registry,
+                "api_rate_limited_total",
+                "Total number of SayHello calls rejected by the rate limit",
+            )?,
+        })
+    }
+
+    /// Swaps the limit; tokens already in the bucket are kept up to the new burst.
+    pub fn configure(&self, settings: Option<RateLimitSettings>) {
+        let mut bucket = self.bucket.lock().unwrap();
+        if let Some(new) = &settings {
+            bucket.tokens = match &bucket.settings {
+                Some(_) => bucket.tokens.min(f64::from(new.burst)),
+                None => f64::from(new.burst),
+            };
+        }
+        bucket.settings = settings;
+    }
+
+    pub fn try_acquire(&self) -> bool {
+        self.try_acquire_at(Instant::now())
+    }
+
+    fn try_acquire_at(&self, now: Instant) -> bool {
+        let mut bucket = self.bucket.lock().unwrap();
+        let Some(settings) = bucket.settings.clone() else {
+            return true;
+        };
+        let elapsed = now
+            .saturating_duration_since(bucket.refilled_at)
+            .as_secs_f64();
+        bucket.tokens =
+            (bucket.tokens + elapsed * settings.requests_per_second).min(f64::from(settings.burst));
+        bucket.refilled_at = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            true
+        } else {
+            self.limited.inc();
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn settings(requests_per_second: f64, burst: u32) -> Option<RateLimitSettings> {
+        Some(RateLimitSettings {
+            requests_per_second,
+            burst,
+        })
+    }
+
+    #[test]
+    fn allows_burst_then_refills() {
+        let limiter = RateLimiter::new(settings(2.0, 3), &Registry::new()).unwrap();
+        let start = Instant::now();
+        assert!((0..3).all(|_| limiter.try_acquire_at(start)));
+        assert!(!limiter.try_acquire_at(start));
+        assert!(limiter.try_acquire_at(start + Duration::from_millis(500)));
+        assert!(!limiter.try_acquire_at(start + Duration::from_millis(600)));
+        assert_eq!(limiter.limited.get(), 2);
+    }
+
+    #[test]
+    fn reconfigures_live() {
+        let limiter = RateLimiter::new(None, &Registry::new()).unwrap();
+        let start = Instant::now();
+        assert!((0..100).all(|_| limiter.try_acquire_at(start)));
+
+        limiter.configure(settings(1.0, 1));
+        assert!(limiter.try_acquire_at(start));
+        assert!(!limiter.try_acquire_at(start));
+
+        limiter.configure(None);
+        assert!(limiter.try_acquire_at(start));
+    }
+
+    #[test]
+    fn rejects_invalid_settings() {
+        assert!(settings(0.0, 1).unwrap().validate().is_err());
+        assert!(settings(1.0, 0).unwrap().validate().is_err());
+        assert!(settings(0.5, 1).unwrap().validate().is_ok());
+    }
+}
@@ -0,0 +1,88 @@
+use crate::grpc_web::GrpcWebSettings;
+use crate::outbox::OutboxSettings;
+use crate::rate_limit::RateLimiter;
+use crate::ServerConfig;
+use prometheus::Registry;
+use std::sync::Arc;
+use terrarium_core::logging::LogHandle;
+use terrarium_core::reload::{ConfigTracker, Live, ReloadFn};
+
+/// Settings applied by a reload; anything else is reported as needing a restart.
+/// `outbox.enabled` is read once at startup.
+const LIVE_SETTINGS: &[&str] = &[
+    "log_level",
+    "rate_limit",
+    "allowed_topics",
+    "grpc_web",
+    "outbox.poll_interval_ms",
+    "outbox.batch_size",
+    "outbox.base_retry_delay_ms",
+    "outbox.max_retry_delay_ms",
+    "outbox.claim_timeout_ms",
+];
+
+/// The parts of `ServerConfig` the running API reads on every use.
+#[derive(Clone)]
+pub struct LiveSettings {
+    pub rate_limiter: Arc<RateLimiter>,
+    pub allowed_topics: Arc<Live<Option<Vec<String>>>>,
+    pub outbox: Arc<Live<OutboxSettings>>,
+    pub grpc_web: Arc<Live<GrpcWebSettings>>,
+}
+
+impl LiveSettings {
+    pub fn new(config: &ServerConfig, registry: &Registry) -> Result<Self, prometheus::Error> {
+        Ok(Self {
+            rate_limiter: Arc::new(RateLimiter::new(config.rate_limit.clone(), registry)?),
+            allowed_topics: Live::new(config.allowed_topics.clone()),
+            outbox: Live::new(config.outbox.clone()),
+            grpc_web: Live::new(config.grpc_web.clone()),
+        })
+    }
+
+    fn apply(&self, config: ServerConfig) {
+        self.rate_limiter.configure(config.rate_limit);
+        self.allowed_topics.set(config.allowed_topics);
+        self.outbox.set(config.outbox);
+        self.grpc_web.set(config.grpc_web);
+    }
+}
+
+/// Re-reads the config on SIGHUP or `POST /admin/reload`.
+pub fn config_reloader(
+    config_str: &str,
+    logs: LogHandle,
+    live: LiveSettings,
+) -> Result<ReloadFn, Box<dyn std::error::Error>> {
+    let tracker = ConfigTracker::new(config_str, LIVE_SETTINGS)?;
+    Ok(Arc::new(move || {
+        let config_str = ServerConfig::read_source().map_err(|e| e.to_string())?;
+        let config = ServerConfig::parse(&config_str).map_err(|e| e.to_string())?;
+        let report = tracker.update(&config_str).map_err(|e| e.to_string())?;
+        logs.set_filter(config.log_level.as_deref());
+        live.apply(config);
+        Ok(report)
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lists_every_outbox_setting_the_relay_reads_live() {
+        let settings = serde_json::to_value(OutboxSettings::default()).unwrap();
+        for field in settings.as_object().unwrap().keys() {
+            // Decides whether the relay runs at all, once at startup.
+            if field == "enabled" {
+                continue;
+            }
+            let setting = format!("outbox.{}", field);
+            assert!(
+                LIVE_SETTINGS.contains(&setting.as_str()),
+                "{} is missing from LIVE_SETTINGS",
+                setting
+            );
+        }
+    }
+}
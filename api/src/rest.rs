@@ -0,0 +1,195 @@
+use crate::proto::{self, hello_api_server::HelloApi, GetMessagesRequest, HelloRequest};
+use crate::MyHelloApi;
+use hyper::header::CONTENT_TYPE;
+use hyper::{body::to_bytes, Body, Method, Request as HttpRequest, Response as HttpResponse};
+use serde::Serialize;
+use serde_json::json;
+use std::sync::Arc;
+use terrarium_core::admin::{self, AdminServer};
+use tonic::{Code, Request, Response, Status};
+
+/// JSON/REST gateway over `HelloApi`, using the proto3 JSON mapping derived in
+/// `common_proto`. The routes must match `HTTP_RULES` in `common_proto/build.rs`,
+/// which generates the OpenAPI document served at `/v1/openapi.json`.
+/// `POST /v1/hello` requires the admin `token` and is not registered without one.
+pub fn routes(server: AdminServer, api: Arc<MyHelloApi>, token: Option<Arc<str>>) -> AdminServer {
+    let hello_api = api.clone();
+    let server = server
+        .route(Method::GET, "/v1/openapi.json", |_| async {
+            HttpResponse::builder()
+                .status(200)
+                .header("Content-Type", "application/json")
+                .body(Body::from(proto::OPENAPI_JSON))
+                .unwrap()
+        })
+        .route(Method::GET, "/v1/messages", move |req| {
+            get_messages(api.clone(), req)
+        });
+    match token {
+        Some(token) => server.route(Method::POST, "/v1/hello", move |req| {
+            say_hello(hello_api.clone(), token.clone(), req)
+        }),
+        None => server,
+    }
+}
+
+/// `POST /v1/hello` with a `HelloRequest` body.
+async fn say_hello(
+    api: Arc<MyHelloApi>,
+    token: Arc<str>,
+    req: HttpRequest<Body>,
+) -> HttpResponse<Body> {
+    if !admin::bearer_authorized(&req, &token) {
+        return error(401, "unauthorized");
+    }
+    if !is_json(&req) {
+        return error(415, "Content-Type must be application/json");
+    }
+    let body = match to_bytes(req.into_body()).await {
+        Ok(body) => body,
+        Err(e) => return error(400, format!("failed to read body: {}", e)),
+    };
+    let request: HelloRequest = match serde_json::from_slice(&body) {
+        Ok(request) => request,
+        Err(e) => return error(400, format!("invalid HelloRequest: {}", e)),
+    };
+    reply(api.say_hello(Request::new(request)).await)
+}
+
+/// `GET /v1/messages?topic=&limit=&jsonPath=`, the `GetMessagesRequest`
+/// fields as query parameters.
+async fn get_messages(api: Arc<MyHelloApi>, req: HttpRequest<Body>) -> HttpResponse<Body> {
+    let mut params = admin::query_params(&req);
+    let limit = match params.remove("limit").map(|limit| limit.parse()) {
+        Some(Ok(limit)) => limit,
+        Some(Err(_)) => return error(400, "limit must be an int32"),
+        None => 0,
+    };
+    let request = GetMessagesRequest {
+        topic: params.remove("topic").unwrap_or_default(),
+        limit,
+        json_path: params
+            .remove("jsonPath")
+            .or_else(|| params.remove("json_path"))
+            .unwrap_or_default(),
+    };
+    reply(api.get_messages(Request::new(request)).await)
+}
+
+fn reply<T: Serialize>(result: Result<Response<T>, Status>) -> HttpResponse<Body> {
+    match result {
+        Ok(response) => admin::json(200, response.get_ref()),
+        Err(status) => status_response(status),
+    }
+}
+
+/// Whether the body is declared as `application/json`. Browsers only send
+/// that cross-site after a CORS preflight, which this server never approves.
+pub fn is_json(req: &HttpRequest<Body>) -> bool {
+    req.headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(';').next())
+        .is_some_and(|mime| mime.trim().eq_ignore_ascii_case("application/json"))
+}
+
+pub fn error(status: u16, message: impl Into<String>) -> HttpResponse<Body> {
+    admin::json(status, &json!({ "error": message.into() }))
+}
+
+/// Reports a gRPC error with the nearest HTTP status.
+pub fn status_response(status: Status) -> HttpResponse<Body> {
+    let code = match status.code() {
+        Code::InvalidArgument => 400,
+        Code::PermissionDenied => 403,
+        Code::NotFound => 404,
+        Code::ResourceExhausted => 429,
+        Code::Unavailable => 503,
+        _ => 500,
+    };
+    error(code, status.message())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::topic_denied;
+    use prometheus::Registry;
+    use serde_json::Value;
+
+    fn request(method: Method, uri: &str, body: &str) -> HttpRequest<Body> {
+        HttpRequest::builder()
+            .method(method)
+            .uri(uri)
+            .header("Authorization", "Bearer s3cret")
+            .header("Content-Type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn serves_openapi_and_rejects_bad_requests() {
+        let server = routes(
+            AdminServer::new("API Dashboard", Registry::new()),
+            crate::tests::offline_api(),
+            Some("s3cret".into()),
+        );
+        let resp = server
+            .handle(request(Method::GET, "/v1/openapi.json", ""))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 200);
+        let doc: Value =
+            serde_json::from_slice(&to_bytes(resp.into_body()).await.unwrap()).unwrap();
+        assert!(doc["paths"]["/v1/messages"]["get"].is_object());
+        assert_eq!(
+            doc["paths"]["/v1/hello"]["post"]["security"],
+            json!([{ "adminToken": [] }])
+        );
+
+        let cases = [
+            (Method::GET, "/v1/messages?topic=payments&limit=5", "", 403),
+            (
+                Method::GET,
+                "/v1/messages?topic=default-topic&limit=x",
+                "",
+                400,
+            ),
+            (Method::POST, "/v1/hello", r#"{"name": 5}"#, 400),
+            (Method::POST, "/v1/hello", "not json", 400),
+        ];
+        for (method, uri, body, status) in cases {
+            let resp = server.handle(request(method, uri, body)).await.unwrap();
+            assert_eq!(resp.status(), status, "{} {}", uri, body);
+            let body: Value =
+                serde_json::from_slice(&to_bytes(resp.into_body()).await.unwrap()).unwrap();
+            assert!(body["error"].is_string());
+        }
+
+        let unauthenticated = HttpRequest::builder()
+            .method(Method::POST)
+            .uri("/v1/hello")
+            .header("Content-Type", "application/json")
+            .body(Body::from(r#"{"name": "Bob"}"#))
+            .unwrap();
+        assert_eq!(server.handle(unauthenticated).await.unwrap().status(), 401);
+        let form = HttpRequest::builder()
+            .method(Method::POST)
+            .uri("/v1/hello")
+            .header("Authorization", "Bearer s3cret")
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .body(Body::from(r#"{"name": "Bob"}"#))
+            .unwrap();
+        assert_eq!(server.handle(form).await.unwrap().status(), 415);
+    }
+
+    #[test]
+    fn maps_grpc_status_to_http() {
+        assert_eq!(
+            status_response(Status::resource_exhausted("slow down")).status(),
+            429
+        );
+        assert_eq!(status_response(topic_denied("payments")).status(), 403);
+        assert_eq!(status_response(Status::internal("boom")).status(), 500);
+    }
+}
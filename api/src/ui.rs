@@ -0,0 +1,369 @@
+use crate::rest::{error, is_json, status_response};
+use crate::{is_invalid_json_path, proto, topic_denied, MessageQuery, MyHelloApi};
+use base64::Engine;
+use chrono::{DateTime, SecondsFormat, TimeZone, Utc};
+use hyper::{body::to_bytes, Body, Method, Request as HttpRequest, Response as HttpResponse};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::Arc;
+use terrarium_core::admin::{self, AdminServer};
+
+/// The single-page UI; it talks to the `/api/messages` endpoints below.
+const INDEX_HTML: &str = include_str!("../ui/index.html");
+
+const DEFAULT_LIMIT: i64 = 50;
+const MAX_LIMIT: i64 = 500;
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SendRequest {
+    name: String,
+}
+
+/// Serves the web UI at `/dashboard`, replacing the built-in stub, along with
+/// the JSON endpoints it uses to list and send messages. Sending requires the
+/// admin `token` and is not registered without one.
+pub fn routes(server: AdminServer, api: Arc<MyHelloApi>, token: Option<Arc<str>>) -> AdminServer {
+    let list_api = api.clone();
+    let server = server
+        .route(Method::GET, "/dashboard", |_| async {
+            admin::html(INDEX_HTML)
+        })
+        .route(Method::GET, "/api/messages", move |req| {
+            list_messages(list_api.clone(), req)
+        });
+    match token {
+        Some(token) => server.route(Method::POST, "/api/messages", move |req| {
+            send_message(api.clone(), token.clone(), req)
+        }),
+        None => server,
+    }
+}
+
+/// `GET /api/messages`: one page of messages, newest first. Pass the returned
+/// `next_cursor` as `cursor` for the following page.
+async fn list_messages(api: Arc<MyHelloApi>, req: HttpRequest<Body>) -> HttpResponse<Body> {
+    let query = match parse_query(&admin::query_params(&req), &api.kafka.topic) {
+        Ok(query) => query,
+        Err(message) => return error(400, message),
+    };
+    if !api.topic_readable(&query.topic) {
+        return status_response(topic_denied(&query.topic));
+    }
+    match api.get_messages_from_db(&query).await {
+        Ok(messages) => {
+            let next_cursor = messages
+                .last()
+                .filter(|_| messages.len() as i64 == query.limit)
+                .and_then(cursor_of);
+            admin::json(
+                200,
+                &json!({
+                    "messages": messages.iter().map(message_json).collect::<Vec<_>>(),
+                    "next_cursor": next_cursor,
+                }),
+            )
+        }
+        Err(e) if is_invalid_json_path(&e) => error(400, format!("invalid json_path: {}", e)),
+        Err(e) => error(500, format!("database error: {}", e)),
+    }
+}
+
+/// `POST /api/messages` with `{"name": ...}`: publishes exactly as `SayHello` does.
+async fn send_message(
+    api: Arc<MyHelloApi>,
+    token: Arc<str>,
+    req: HttpRequest<Body>,
+) -> HttpResponse<Body> {
+    if !admin::bearer_authorized(&req, &token) {
+        return error(401, "unauthorized");
+    }
+    if !is_json(&req) {
+        return error(415, "Content-Type must be application/json");
+    }
+    let body = match to_bytes(req.into_body()).await {
+        Ok(body) => body,
+        Err(e) => return error(400, format!("failed to read body: {}", e)),
+    };
+    let request: SendRequest = match serde_json::from_slice(&body) {
+        Ok(request) => request,
+        Err(e) => return error(400, format!("invalid request: {}", e)),
+    };
+    let name = request.name.trim().to_string();
+    if name.is_empty() {
+        return error(400, "name is required");
+    }
+    match api.publish_hello(&name).await {
+        Ok(receipt) => admin::json(
+            200,
+            &json!({
+                "message": format!("Hello {}!", name),
+                "event_id": receipt.event_id.to_string(),
+                "topic": receipt.topic,
+                "partition": receipt.partition,
+                "offset": receipt.offset,
+            }),
+        ),
+        Err(status) => status_response(status),
+    }
+}
+
+/// Builds a `MessageQuery` from `topic`, `since`, `until` (RFC 3339),
+/// `cursor`, `after_id`, `limit` and `json_path` parameters.
+fn parse_query(
+    params: &HashMap<String, String>,
+    default_topic: &str,
+) -> Result<MessageQuery, String> {
+    let param = |name: &str| {
+        params
+            .get(name)
+            .map(|value| value.trim())
+            .filter(|value| !value.is_empty())
+    };
+    let time = |name: &str| {
+        param(name)
+            .map(|value| {
+                DateTime::parse_from_rfc3339(value)
+                    .map(|t| t.with_timezone(&Utc))
+                    .map_err(|e| format!("invalid {}: {}", name, e))
+            })
+            .transpose()
+    };
+    let limit = match param("limit") {
+        Some(value) => value
+            .parse::<i64>()
+            .ok()
+            .filter(|limit| (1..=MAX_LIMIT).contains(limit))
+            .ok_or_else(|| format!("limit must be between 1 and {}", MAX_LIMIT))?,
+        None => DEFAULT_LIMIT,
+    };
+    Ok(MessageQuery {
+        json_path: param("json_path").map(String::from),
+        since: time("since")?,
+        until: time("until")?,
+        before: param("cursor")
+            .map(|value| parse_cursor(value).ok_or("invalid cursor"))
+            .transpose()?,
+        after_id: param("after_id")
+            .map(|value| value.parse().map_err(|_| "invalid after_id"))
+            .transpose()?,
+        ..MessageQuery::new(param("topic").unwrap_or(default_topic), limit)
+    })
+}
+
+/// Cursors are `<created_at in microseconds>_<id>`; Postgres keeps
+/// microseconds, so they round-trip exactly.
+fn cursor_of(message: &proto::Message) -> Option<String> {
+    let created_at = message.created_at.as_ref()?;
+    let micros = created_at.seconds * 1_000_000 + i64::from(created_at.nanos / 1_000);
+    Some(format!("{}_{}", micros, message.id))
+}
+
+fn parse_cursor(cursor: &str) -> Option<(DateTime<Utc>, i64)> {
+    let (micros, id) = cursor.split_once('_')?;
+    let created_at = Utc.timestamp_micros(micros.parse().ok()?).single()?;
+    Some((created_at, id.parse().ok()?))
+}
+
+fn timestamp_json(timestamp: Option<&prost_types::Timestamp>) -> Value {
+    timestamp
+        .and_then(|t| Utc.timestamp_opt(t.seconds, t.nanos as u32).single())
+        .map_or(Value::Null, |t| {
+            json!(t.to_rfc3339_opts(SecondsFormat::AutoSi, true))
+        })
+}
+
+fn message_json(message: &proto::Message) -> Value {
+    json!({
+        "id": message.id,
+        "topic": message.topic,
+        "partition": message.part,
+        "offset": message.kafkaoffset,
+        "event_id": Some(&message.event_id).filter(|id| !id.is_empty()),
+        "key": String::from_utf8_lossy(&message.key),
+        "headers": message
+            .headers
+            .iter()
+            .map(|h| {
+                let binary = (!h.value_bytes.is_empty())
+                    .then(|| base64::engine::general_purpose::STANDARD.encode(&h.value_bytes));
+                json!({"key": h.key, "value": h.value, "value_base64": binary})
+            })
+            .collect::<Vec<_>>(),
+        "content_type": message.content_type,
+        "payload": message.payload,
+        "created_at": timestamp_json(message.created_at.as_ref()),
+        "kafka_timestamp": timestamp_json(message.kafka_timestamp.as_ref()),
+        "produced_at": timestamp_json(message.produced_at.as_ref()),
+        "consumed_at": timestamp_json(message.consumed_at.as_ref()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prometheus::Registry;
+
+    fn params(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    fn server(token: Option<&str>) -> AdminServer {
+        routes(
+            AdminServer::new("API Dashboard", Registry::new()),
+            crate::tests::offline_api(),
+            token.map(Into::into),
+        )
+    }
+
+    fn request(method: Method, uri: &str, body: &str) -> HttpRequest<Body> {
+        HttpRequest::builder()
+            .method(method)
+            .uri(uri)
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    }
+
+    fn send(token: Option<&str>, content_type: Option<&str>, body: &str) -> HttpRequest<Body> {
+        let mut builder = HttpRequest::builder()
+            .method(Method::POST)
+            .uri("/api/messages");
+        if let Some(token) = token {
+            builder = builder.header("Authorization", format!("Bearer {}", token));
+        }
+        if let Some(content_type) = content_type {
+            builder = builder.header("Content-Type", content_type);
+        }
+        builder.body(Body::from(body.to_string())).unwrap()
+    }
+
+    #[test]
+    fn parses_message_filters() {
+        let query = parse_query(&params(&[]), "default-topic").unwrap();
+        assert_eq!(query, MessageQuery::new("default-topic", DEFAULT_LIMIT));
+
+        let query = parse_query(
+            &params(&[
+                ("topic", "payments"),
+                ("since", "2026-01-01T00:00:00Z"),
+                ("until", "2026-01-02T00:00:00+01:00"),
+                ("cursor", "1767225600000001_42"),
+                ("after_id", "7"),
+                ("limit", "10"),
+                ("json_path", " "),
+            ]),
+            "default-topic",
+        )
+        .unwrap();
+        assert_eq!(query.topic, "payments");
+        assert_eq!(query.limit, 10);
+        assert_eq!(query.json_path, None);
+        assert_eq!(
+            query.since.unwrap().to_rfc3339(),
+            "2026-01-01T00:00:00+00:00"
+        );
+        assert_eq!(
+            query.until.unwrap().to_rfc3339(),
+            "2026-01-01T23:00:00+00:00"
+        );
+        let (created_at, id) = query.before.unwrap();
+        assert_eq!(created_at.timestamp_subsec_micros(), 1);
+        assert_eq!(id, 42);
+        assert_eq!(query.after_id, Some(7));
+
+        for bad in [
+            ("limit", "0"),
+            ("limit", "501"),
+            ("since", "yesterday"),
+            ("cursor", "42"),
+            ("after_id", "x"),
+        ] {
+            assert!(parse_query(&params(&[bad]), "t").is_err(), "{:?}", bad);
+        }
+    }
+
+    #[test]
+    fn cursor_round_trips_message_position() {
+        let message = proto::Message {
+            id: 9,
+            created_at: Some(prost_types::Timestamp {
+                seconds: 1_709_985_600,
+                nanos: 123_456_000,
+            }),
+            ..Default::default()
+        };
+        let cursor = cursor_of(&message).unwrap();
+        assert_eq!(cursor, "1709985600123456_9");
+        let (created_at, id) = parse_cursor(&cursor).unwrap();
+        assert_eq!(
+            created_at.to_rfc3339_opts(SecondsFormat::Micros, true),
+            "2024-03-09T12:00:00.123456Z"
+        );
+        assert_eq!(id, 9);
+        assert_eq!(
+            message_json(&message)["created_at"],
+            "2024-03-09T12:00:00.123456Z"
+        );
+        assert_eq!(message_json(&message)["event_id"], Value::Null);
+    }
+
+    #[tokio::test]
+    async fn serves_ui_and_rejects_bad_requests() {
+        let server = server(Some("s3cret"));
+        let resp = server
+            .handle(request(Method::GET, "/dashboard", ""))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 200);
+        let body = to_bytes(resp.into_body()).await.unwrap();
+        assert!(String::from_utf8_lossy(&body).contains("/api/messages"));
+
+        let cases = [
+            (Method::GET, "/api/messages?topic=payments", "", 403),
+            (Method::GET, "/api/messages?limit=abc", "", 400),
+        ];
+        for (method, uri, body, status) in cases {
+            let resp = server.handle(request(method, uri, body)).await.unwrap();
+            assert_eq!(resp.status(), status, "{} {}", uri, body);
+        }
+
+        let json = Some("application/json; charset=utf-8");
+        let sends = [
+            (None, json, r#"{"name": "Bob"}"#, 401),
+            (Some("wrong"), json, r#"{"name": "Bob"}"#, 401),
+            (Some("s3cret"), None, r#"{"name": "Bob"}"#, 415),
+            (
+                Some("s3cret"),
+                Some("text/plain"),
+                r#"{"name": "Bob"}"#,
+                415,
+            ),
+            (Some("s3cret"), json, r#"{"name": "  "}"#, 400),
+            (Some("s3cret"), json, "not json", 400),
+        ];
+        for (token, content_type, body, status) in sends {
+            let resp = server
+                .handle(send(token, content_type, body))
+                .await
+                .unwrap();
+            assert_eq!(
+                resp.status(),
+                status,
+                "{:?} {:?} {}",
+                token,
+                content_type,
+                body
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn sending_requires_an_admin_token() {
+        let req = send(None, Some("application/json"), r#"{"name": "Bob"}"#);
+        assert_eq!(server(None).handle(req).await.unwrap().status(), 404);
+    }
+}
@@ -1,11 +1,283 @@
-use std::{env, path::PathBuf};
+use prost::Message;
+use prost_types::field_descriptor_proto::{Label, Type};
+use prost_types::{DescriptorProto, FieldDescriptorProto, FileDescriptorProto, FileDescriptorSet};
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+use std::process::Command;
+use std::{env, fs, path::PathBuf};
+
+const PROTO: &str = "proto/hello.proto";
+
+/// REST routes served by the API's HTTP gateway (`api/src/rest.rs`), as
+/// `(rpc, HTTP method, path)`. POST routes take the request message as the
+/// JSON body and require the admin token, GET routes take query parameters.
+const HTTP_RULES: &[(&str, &str, &str)] = &[
+    ("SayHello", "post", "/v1/hello"),
+    ("GetMessages", "get", "/v1/messages"),
+];
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
-    tonic_build::configure()
-        .file_descriptor_set_path(out_dir.join("hello_descriptor.bin"))
-        .compile(&["proto/hello.proto"], &["proto"])
-        .unwrap();
+    let descriptor_path = out_dir.join("hello_descriptor.bin");
+
+    // protoc runs once up front so the descriptors can drive both the serde
+    // attributes and the OpenAPI document; tonic-build then reuses its output.
+    let mut protoc = Command::new(prost_build::protoc_from_env());
+    protoc
+        .args(["--include_imports", "--include_source_info", "-I", "proto"])
+        .arg("-o")
+        .arg(&descriptor_path)
+        .arg(PROTO);
+    if let Some(include) = prost_build::protoc_include_from_env() {
+        protoc.arg("-I").arg(include);
+    }
+    let output = protoc.output()?;
+    if !output.status.success() {
+        return Err(format!("protoc failed: {}", String::from_utf8_lossy(&output.stderr)).into());
+    }
+    let descriptors = FileDescriptorSet::decode(&*fs::read(&descriptor_path)?)?;
+    let file = descriptors
+        .file
+        .iter()
+        .find(|f| f.package() == "hello")
+        .ok_or("hello.proto has no hello package")?;
 
+    let mut builder = tonic_build::configure()
+        .file_descriptor_set_path(&descriptor_path)
+        .skip_protoc_run()
+        .message_attribute(
+            ".hello",
+            "#[derive(serde::Serialize, serde::Deserialize)] #[serde(default, rename_all = \"camelCase\")]",
+        )
+        .enum_attribute(
+            ".hello",
+            "#[derive(serde::Serialize, serde::Deserialize)] #[serde(rename_all = \"camelCase\")]",
+        );
+    let mut field_attributes = BTreeMap::new();
+    for message in &file.message_type {
+        for field in &message.field {
+            if let Some(attribute) = serde_field_attribute(message, field) {
+                // Unanchored paths, so a oneof's attribute isn't also applied
+                // to its variants.
+                let name = real_oneof(message, field).unwrap_or(field.name());
+                field_attributes.insert(format!("hello.{}.{}", message.name(), name), attribute);
+            }
+        }
+    }
+    for (path, attribute) in field_attributes {
+        builder = builder.field_attribute(path, attribute);
+    }
+    builder.compile(&[PROTO], &["proto"])?;
+
+    fs::write(
+        out_dir.join("openapi.json"),
+        serde_json::to_string_pretty(&openapi(file)?)?,
+    )?;
     Ok(())
 }
+
+/// The oneof `field` belongs to, unless it is a proto3 `optional` field.
+fn real_oneof<'a>(message: &'a DescriptorProto, field: &FieldDescriptorProto) -> Option<&'a str> {
+    if field.proto3_optional() {
+        return None;
+    }
+    let index = field.oneof_index? as usize;
+    Some(message.oneof_decl[index].name())
+}
+
+/// Applies the proto3 JSON mapping where serde's default encoding differs.
+/// Oneofs are flattened into their message.
+fn serde_field_attribute(
+    message: &DescriptorProto,
+    field: &FieldDescriptorProto,
+) -> Option<&'static str> {
+    if real_oneof(message, field).is_some() {
+        return Some("#[serde(flatten)]");
+    }
+    let with = match (field.r#type(), field.type_name()) {
+        (Type::Int64 | Type::Sint64 | Type::Sfixed64 | Type::Uint64 | Type::Fixed64, _) => {
+            "#[serde(with = \"crate::json::int64\")]"
+        }
+        (Type::Bytes, _) => "#[serde(with = \"crate::json::bytes\")]",
+        (Type::Message, ".google.protobuf.Timestamp") => {
+            "#[serde(with = \"crate::json::timestamp\")]"
+        }
+        _ => return None,
+    };
+    if field.label() == Label::Repeated {
+        panic!(
+            "{}.{}: repeated {:?} fields have no JSON mapping yet",
+            message.name(),
+            field.name(),
+            field.r#type()
+        );
+    }
+    Some(with)
+}
+
+/// Leading (or else trailing) comment attached to the descriptor at `path`.
+fn comment(file: &FileDescriptorProto, path: &[i32]) -> Option<String> {
+    let location = file
+        .source_code_info
+        .as_ref()?
+        .location
+        .iter()
+        .find(|l| l.path == path)?;
+    let text = location
+        .leading_comments
+        .as_deref()
+        .or(location.trailing_comments.as_deref())?;
+    let text = text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ");
+    Some(text).filter(|t| !t.is_empty())
+}
+
+fn schema_ref(type_name: &str) -> Value {
+    let name = type_name.trim_start_matches(".hello.");
+    json!({ "$ref": format!("#/components/schemas/{}", name) })
+}
+
+fn field_schema(field: &FieldDescriptorProto, description: Option<String>) -> Value {
+    let mut schema = match (field.r#type(), field.type_name()) {
+        (Type::Double | Type::Float, _) => json!({ "type": "number" }),
+        (Type::Int32 | Type::Sint32 | Type::Sfixed32, _) => {
+            json!({ "type": "integer", "format": "int32" })
+        }
+        (Type::Uint32 | Type::Fixed32, _) => json!({ "type": "integer", "minimum": 0 }),
+        (Type::Int64 | Type::Sint64 | Type::Sfixed64 | Type::Uint64 | Type::Fixed64, _) => {
+            json!({ "type": "string", "format": "int64" })
+        }
+        (Type::Bool, _) => json!({ "type": "boolean" }),
+        (Type::String | Type::Enum, _) => json!({ "type": "string" }),
+        (Type::Bytes, _) => json!({ "type": "string", "format": "byte" }),
+        (Type::Message, ".google.protobuf.Timestamp") => {
+            json!({ "type": "string", "format": "date-time" })
+        }
+        (Type::Message | Type::Group, type_name) => json!({ "allOf": [schema_ref(type_name)] }),
+    };
+    if field.label() == Label::Repeated {
+        schema = json!({ "type": "array", "items": schema });
+    }
+    let object = schema.as_object_mut().unwrap();
+    if let Some(description) = description {
+        object.insert("description".into(), json!(description));
+    }
+    if field.options.as_ref().is_some_and(|o| o.deprecated()) {
+        object.insert("deprecated".into(), json!(true));
+    }
+    schema
+}
+
+/// OpenAPI 3 description of the REST gateway, derived from `hello.proto`.
+fn openapi(file: &FileDescriptorProto) -> Result<Value, Box<dyn std::error::Error>> {
+    let mut schemas = Map::new();
+    for (i, message) in file.message_type.iter().enumerate() {
+        let properties: Map<String, Value> = message
+            .field
+            .iter()
+            .enumerate()
+            .map(|(j, field)| {
+                let description = comment(file, &[4, i as i32, 2, j as i32]);
+                (
+                    field.json_name().to_string(),
+                    field_schema(field, description),
+                )
+            })
+            .collect();
+        let mut schema = json!({ "type": "object", "properties": properties });
+        if let Some(description) = comment(file, &[4, i as i32]) {
+            schema["description"] = json!(description);
+        }
+        if !message.oneof_decl.is_empty() {
+            let oneofs: Vec<&str> = message
+                .field
+                .iter()
+                .filter(|f| real_oneof(message, f).is_some())
+                .map(|f| f.json_name())
+                .collect();
+            if !oneofs.is_empty() {
+                schema["description"] =
+                    json!(format!("Set exactly one of: {}.", oneofs.join(", ")));
+            }
+        }
+        schemas.insert(message.name().to_string(), schema);
+    }
+    schemas.insert(
+        "Error".to_string(),
+        json!({
+            "type": "object",
+            "properties": { "error": { "type": "string" } },
+            "required": ["error"],
+        }),
+    );
+
+    let service = file.service.first().ok_or("hello.proto has no service")?;
+    let mut paths = Map::new();
+    for (rpc, method, path) in HTTP_RULES {
+        let rpc_descriptor = service
+            .method
+            .iter()
+            .find(|m| m.name() == *rpc)
+            .ok_or_else(|| format!("HTTP_RULES names unknown rpc {}", rpc))?;
+        let input = rpc_descriptor.input_type().trim_start_matches(".hello.");
+        let mut operation = json!({
+            "operationId": rpc,
+            "summary": format!("{}.{}", service.name(), rpc),
+            "responses": {
+                "200": {
+                    "description": "OK",
+                    "content": { "application/json": { "schema": schema_ref(rpc_descriptor.output_type()) } },
+                },
+                "default": {
+                    "description": "Error, with the gRPC status mapped to an HTTP status",
+                    "content": { "application/json": { "schema": schema_ref("Error") } },
+                },
+            },
+        });
+        if *method == "get" {
+            let i = file
+                .message_type
+                .iter()
+                .position(|m| m.name() == input)
+                .ok_or_else(|| format!("unknown message {}", input))?;
+            operation["parameters"] = file.message_type[i]
+                .field
+                .iter()
+                .enumerate()
+                .map(|(j, field)| {
+                    let description = comment(file, &[4, i as i32, 2, j as i32]);
+                    json!({
+                        "name": field.json_name(),
+                        "in": "query",
+                        "schema": field_schema(field, description),
+                    })
+                })
+                .collect();
+        } else {
+            operation["requestBody"] = json!({
+                "required": true,
+                "content": { "application/json": { "schema": schema_ref(input) } },
+            });
+            operation["security"] = json!([{ "adminToken": [] }]);
+        }
+        paths.insert(path.to_string(), json!({ *method: operation }));
+    }
+
+    Ok(json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": service.name(),
+            "version": env::var("CARGO_PKG_VERSION")?,
+            "description": "JSON/REST gateway to the gRPC HelloApi. Messages use the proto3 JSON mapping.",
+        },
+        "paths": paths,
+        "components": {
+            "schemas": schemas,
+            "securitySchemes": { "adminToken": { "type": "http", "scheme": "bearer" } },
+        },
+    }))
+}
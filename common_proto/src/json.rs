@@ -0,0 +1,166 @@
+//! serde helpers giving the generated types the proto3 JSON mapping where it
+//! differs from serde's defaults. `build.rs` attaches them to fields by type.
+
+/// 64-bit integers are written as strings; numbers are accepted too.
+pub mod int64 {
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::fmt::Display;
+    use std::str::FromStr;
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Repr<T> {
+        Number(T),
+        Text(String),
+    }
+
+    pub fn serialize<T: Display, S: Serializer>(
+        value: &T,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(value)
+    }
+
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+    where
+        T: FromStr + Deserialize<'de>,
+        T::Err: Display,
+        D: Deserializer<'de>,
+    {
+        match Repr::<T>::deserialize(deserializer)? {
+            Repr::Number(value) => Ok(value),
+            Repr::Text(text) => text.parse().map_err(Error::custom),
+        }
+    }
+}
+
+/// Bytes are written as standard base64.
+pub mod bytes {
+    use base64::engine::general_purpose::STANDARD;
+    use base64::Engine;
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&STANDARD.encode(value))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        STANDARD.decode(text).map_err(Error::custom)
+    }
+}
+
+/// `google.protobuf.Timestamp` fields are written as RFC 3339 in UTC.
+pub mod timestamp {
+    use chrono::{DateTime, SecondsFormat, TimeZone, Utc};
+    use prost_types::Timestamp;
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        value: &Option<Timestamp>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let Some(timestamp) = value else {
+            return serializer.serialize_none();
+        };
+        let time = u32::try_from(timestamp.nanos)
+            .ok()
+            .and_then(|nanos| Utc.timestamp_opt(timestamp.seconds, nanos).single())
+            .ok_or_else(|| serde::ser::Error::custom("timestamp out of range"))?;
+        serializer.serialize_str(&time.to_rfc3339_opts(SecondsFormat::AutoSi, true))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Timestamp>, D::Error> {
+        let Some(text) = Option::<String>::deserialize(deserializer)? else {
+            return Ok(None);
+        };
+        let time = DateTime::parse_from_rfc3339(&text).map_err(Error::custom)?;
+        Ok(Some(Timestamp {
+            seconds: time.timestamp(),
+            nanos: time.timestamp_subsec_nanos() as i32,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::proto::{self, get_message_request::Lookup, GetMessageRequest, MessageHeader};
+    use serde_json::{json, Value};
+
+    #[test]
+    fn encodes_messages_with_proto3_json_mapping() {
+        let message = proto::Message {
+            id: 1 << 40,
+            topic: "default-topic".to_string(),
+            kafkaoffset: 7,
+            key: b"Bob".to_vec(),
+            headers: vec![MessageHeader {
+                key: "trace-id".to_string(),
+                value: None,
+                value_bytes: vec![0xff],
+            }],
+            created_at: Some(prost_types::Timestamp {
+                seconds: 1_709_985_600,
+                nanos: 500_000_000,
+            }),
+            ..Default::default()
+        };
+        let value = serde_json::to_value(&message).unwrap();
+        assert_eq!(value["id"], "1099511627776");
+        assert_eq!(value["kafkaoffset"], "7");
+        assert_eq!(value["key"], "Qm9i");
+        assert_eq!(value["createdAt"], "2024-03-09T12:00:00.500Z");
+        assert_eq!(value["producedAt"], Value::Null);
+        assert_eq!(
+            value["headers"],
+            json!([{"key": "trace-id", "value": null, "valueBytes": "/w=="}])
+        );
+
+        let decoded: proto::Message = serde_json::from_value(value).unwrap();
+        assert_eq!(decoded, message);
+    }
+
+    #[test]
+    fn decodes_partial_requests_and_oneofs() {
+        let request: proto::GetMessagesRequest =
+            serde_json::from_str(r#"{"topic": "default-topic", "jsonPath": "$.name"}"#).unwrap();
+        assert_eq!(request.limit, 0);
+        assert_eq!(request.json_path, "$.name");
+
+        let lookup: GetMessageRequest =
+            serde_json::from_str(r#"{"coordinates": {"topic": "t", "partition": 1, "offset": 2}}"#)
+                .unwrap();
+        assert!(matches!(lookup.lookup, Some(Lookup::Coordinates(c)) if c.offset == 2));
+        let lookup: GetMessageRequest = serde_json::from_str(r#"{"eventId": "abc"}"#).unwrap();
+        assert_eq!(lookup.lookup, Some(Lookup::EventId("abc".to_string())));
+    }
+
+    #[test]
+    fn generates_openapi_for_rest_routes() {
+        let doc: Value = serde_json::from_str(proto::OPENAPI_JSON).unwrap();
+        assert_eq!(doc["openapi"], "3.0.3");
+        assert_eq!(
+            doc["paths"]["/v1/hello"]["post"]["requestBody"]["content"]["application/json"]
+                ["schema"]["$ref"],
+            "#/components/schemas/HelloRequest"
+        );
+        let params = doc["paths"]["/v1/messages"]["get"]["parameters"]
+            .as_array()
+            .unwrap();
+        let names: Vec<&str> = params.iter().map(|p| p["name"].as_str().unwrap()).collect();
+        assert_eq!(names, ["topic", "limit", "jsonPath"]);
+        let message = &doc["components"]["schemas"]["Message"]["properties"];
+        assert_eq!(message["id"]["format"], "int64");
+        assert_eq!(message["createdAt"]["format"], "date-time");
+        assert_eq!(message["createdAtText"]["deprecated"], true);
+        assert!(message["contentType"]["description"]
+            .as_str()
+            .unwrap()
+            .starts_with("MIME type"));
+    }
+}
@@ -1,5 +1,10 @@
+pub mod json;
+
 pub mod proto {
     tonic::include_proto!("hello");
 
     pub const FILE_DESCRIPTOR_SET: &[u8] = tonic::include_file_descriptor_set!("hello_descriptor");
+
+    /// OpenAPI document for the REST gateway, generated from `hello.proto`.
+    pub const OPENAPI_JSON: &str = include_str!(concat!(env!("OUT_DIR"), "/openapi.json"));
 }
@@ -0,0 +1,256 @@
+use prometheus::{IntCounter, IntGauge, Registry};
+use serde::Deserialize;
+use std::future::Future;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use terrarium_core::metrics;
+use tokio::sync::watch;
+use tokio::time::sleep;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct BreakerSettings {
+    /// Consecutive failed inserts, with Postgres unreachable, that open the circuit.
+    pub failure_threshold: u32,
+    /// How often Postgres is probed while the circuit is open.
+    pub probe_interval_secs: u64,
+}
+
+impl Default for BreakerSettings {
+    fn default() -> Self {
+        Self {
+            failure_threshold: 5,
+            probe_interval_secs: 5,
+        }
+    }
+}
+
+impl BreakerSettings {
+    pub fn validate(&self) -> Result<(), Box<dyn std::error::Error>> {
+        if self.failure_threshold == 0 {
+            return Err("circuit_breaker.failure_threshold must be greater than 0".into());
+        }
+        if self.probe_interval_secs == 0 {
+            return Err("circuit_breaker.probe_interval_secs must be greater than 0".into());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BreakerState {
+    /// Postgres is healthy and inserts go through.
+    Closed,
+    /// Postgres is failing; partitions are paused and inserts wait.
+    Open,
+    /// A probe is in flight.
+    HalfOpen,
+}
+
+impl BreakerState {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            BreakerState::Closed => "closed",
+            BreakerState::Open => "open",
+            BreakerState::HalfOpen => "half-open",
+        }
+    }
+
+    /// Value of the `consumer_db_circuit_state` gauge.
+    fn metric_value(self) -> i64 {
+        match self {
+            BreakerState::Closed => 0,
+            BreakerState::Open => 1,
+            BreakerState::HalfOpen => 2,
+        }
+    }
+}
+
+/// Tracks whether Postgres is reachable. The postgres sink reports each insert,
+/// the main loop pauses and resumes partitions on state changes, and
+/// [`CircuitBreaker::run_probes`] closes the circuit again once Postgres answers.
+pub struct CircuitBreaker {
+    settings: BreakerSettings,
+    state: watch::Sender<BreakerState>,
+    consecutive_failures: AtomicU32,
+    state_gauge: IntGauge,
+    opened: IntCounter,
+}
+
+impl CircuitBreaker {
+    pub fn new(
+        settings: BreakerSettings,
+        registry: &Registry,
+    ) -> Result<Arc<Self>, prometheus::Error> {
+        Ok(Arc::new(Self {
+            settings,
+            state: watch::Sender::new(BreakerState::Closed),
+            consecutive_failures: AtomicU32::new(0),
+            state_gauge: metrics::register_int_gauge(
+                registry,
+                "consumer_db_circuit_state",
+                "Postgres circuit breaker state: 0 closed, 1 open, 2 half-open",
+            )?,
+            opened: metrics::register_int_counter(
+                registry,
+                "consumer_db_circuit_opened_total",
+                "Total number of times the Postgres circuit breaker opened",
+            )?,
+        }))
+    }
+
+    pub fn state(&self) -> BreakerState {
+        *self.state.borrow()
+    }
+
+    pub fn subscribe(&self) -> watch::Receiver<BreakerState> {
+        self.state.subscribe()
+    }
+
+    fn set_state(&self, state: BreakerState) {
+        self.state.send_if_modified(|current| {
+            if *current == state {
+                return false;
+            }
+            log::info!(
+                "Postgres circuit breaker {} -> {}",
+                current.as_str(),
+                state.as_str()
+            );
+            *current = state;
+            true
+        });
+        self.state_gauge.set(state.metric_value());
+    }
+
+    pub fn record_success(&self) {
+        self.consecutive_failures.store(0, Ordering::SeqCst);
+        self.set_state(BreakerState::Closed);
+    }
+
+    /// Records an insert that failed because Postgres was unreachable.
+    pub fn record_failure(&self) {
+        let failures = self.consecutive_failures.fetch_add(1, Ordering::SeqCst) + 1;
+        if failures >= self.settings.failure_threshold && self.state() == BreakerState::Closed {
+            log::error!(
+                "Opening Postgres circuit breaker after {} consecutive failures",
+                failures
+            );
+            self.opened.inc();
+            self.set_state(BreakerState::Open);
+        }
+    }
+
+    pub async fn wait_until_closed(&self) {
+        let mut state = self.subscribe();
+        // The sender lives as long as `self`, so this cannot fail.
+        let _ = state.wait_for(|s| *s == BreakerState::Closed).await;
+    }
+
+    pub async fn wait_until_open(&self) {
+        let mut state = self.subscribe();
+        let _ = state.wait_for(|s| *s != BreakerState::Closed).await;
+    }
+
+    /// Runs forever: whenever the circuit is open, calls `probe` every
+    /// `probe_interval_secs` and closes the circuit on the first success.
+    pub async fn run_probes<F, Fut, E>(self: Arc<Self>, probe: F)
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<(), E>>,
+        E: std::fmt::Display,
+    {
+        let interval = Duration::from_secs(self.settings.probe_interval_secs);
+        loop {
+            self.wait_until_open().await;
+            sleep(interval).await;
+            self.set_state(BreakerState::HalfOpen);
+            match probe().await {
+                Ok(()) => self.record_success(),
+                Err(e) => {
+                    log::warn!("Postgres probe failed: {}", e);
+                    self.set_state(BreakerState::Open);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicBool;
+
+    fn breaker(registry: &Registry) -> Arc<CircuitBreaker> {
+        CircuitBreaker::new(
+            BreakerSettings {
+                failure_threshold: 3,
+                probe_interval_secs: 5,
+            },
+            registry,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn opens_after_consecutive_failures() {
+        let registry = Registry::new();
+        let breaker = breaker(&registry);
+        breaker.record_failure();
+        breaker.record_failure();
+        breaker.record_success();
+        breaker.record_failure();
+        breaker.record_failure();
+        assert_eq!(breaker.state(), BreakerState::Closed);
+
+        breaker.record_failure();
+        assert_eq!(breaker.state(), BreakerState::Open);
+        assert_eq!(breaker.state_gauge.get(), 1);
+        assert_eq!(breaker.opened.get(), 1);
+
+        breaker.record_success();
+        assert_eq!(breaker.state(), BreakerState::Closed);
+        assert_eq!(breaker.state_gauge.get(), 0);
+    }
+
+    #[test]
+    fn validates_settings() {
+        assert!(BreakerSettings::default().validate().is_ok());
+        let settings = BreakerSettings {
+            failure_threshold: 0,
+            ..Default::default()
+        };
+        assert!(settings.validate().is_err());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn probes_until_postgres_answers() {
+        let breaker = breaker(&Registry::new());
+        let healthy = Arc::new(AtomicBool::new(false));
+        tokio::spawn(breaker.clone().run_probes({
+            let healthy = healthy.clone();
+            move || {
+                let healthy = healthy.load(Ordering::SeqCst);
+                async move {
+                    if healthy {
+                        Ok(())
+                    } else {
+                        Err("connection refused")
+                    }
+                }
+            }
+        }));
+
+        for _ in 0..3 {
+            breaker.record_failure();
+        }
+        sleep(Duration::from_secs(12)).await;
+        assert_eq!(breaker.state(), BreakerState::Open);
+
+        healthy.store(true, Ordering::SeqCst);
+        tokio::time::timeout(Duration::from_secs(6), breaker.wait_until_closed())
+            .await
+            .expect("probe should close the circuit");
+    }
+}
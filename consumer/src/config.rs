@@ -1,21 +1,110 @@
-use crate::db::DatabaseSettings;
+use crate::breaker::BreakerSettings;
+use crate::partitions::PartitionSettings;
+use crate::pipeline::{Pipeline, Stage};
+use crate::rebalance::AssignmentStrategy;
+use crate::sink::{self, RetrySettings, SinkSettings};
+use crate::validation::ValidationSettings;
+use crate::workers::ProcessingSettings;
 use log::info;
 use serde::Deserialize;
+use terrarium_core::admin::AdminSettings;
+use terrarium_core::config;
+use terrarium_core::database::DatabaseSettings;
+use terrarium_core::kafka_security::KafkaSecurity;
 
 #[derive(Debug, Deserialize)]
 pub struct ConsumerConfig {
     pub kafka_broker: String,
     pub group_id: String,
     pub topic: String,
-    pub database: DatabaseSettings,
+    /// Required when the postgres sink is used (the default) and for `migrate`.
+    pub database: Option<DatabaseSettings>,
+    #[serde(default)]
+    pub kafka_security: KafkaSecurity,
+    /// Apply pending schema migrations before consuming.
+    #[serde(default = "default_run_migrations")]
+    pub run_migrations: bool,
+    #[serde(default)]
+    pub partitions: PartitionSettings,
+    /// Where consumed records are written, in order.
+    #[serde(default = "sink::default_sinks")]
+    pub sinks: Vec<SinkSettings>,
+    /// Transformations applied to each record before it is written.
+    #[serde(default)]
+    pub pipeline: Vec<Stage>,
+    /// Per-topic JSON Schemas and where rejected records are quarantined.
+    #[serde(default)]
+    pub validation: ValidationSettings,
+    #[serde(default)]
+    pub processing: ProcessingSettings,
+    /// When to pause consumption because Postgres is failing.
+    #[serde(default)]
+    pub circuit_breaker: BreakerSettings,
+    /// `partition.assignment.strategy`; librdkafka's default when unset.
+    #[serde(default)]
+    pub assignment_strategy: Option<AssignmentStrategy>,
+    /// Enables the authenticated `/admin/*` endpoints on the metrics port.
+    #[serde(default)]
+    pub admin: Option<AdminSettings>,
+    /// `RUST_LOG`-style filter; overrides `RUST_LOG` and is applied on reload.
+    #[serde(default)]
+    pub log_level: Option<String>,
+    /// Retries for failed sink writes; applied on reload.
+    #[serde(default)]
+    pub sink_retry: RetrySettings,
+}
+
+fn default_run_migrations() -> bool {
+    true
 }
 
 impl ConsumerConfig {
+    /// Loads the config from `$CONSUMER_CONFIG` if set, otherwise from `config.json`.
+    pub fn load() -> Result<Self, Box<dyn std::error::Error>> {
+        Self::new(&Self::read_source()?)
+    }
+
+    pub fn read_source() -> Result<String, Box<dyn std::error::Error>> {
+        config::read_source("CONSUMER_CONFIG", "config.json")
+    }
+
     pub fn new(config_str: &str) -> Result<Self, Box<dyn std::error::Error>> {
-        let config: Self = serde_json::from_str(config_str)?;
+        let config = config::parse_json(config_str, |config: &Self| {
+            config.kafka_security.validate()?;
+            if let Some(database) = &config.database {
+                database.validate()?;
+            }
+            config.partitions.validate()?;
+            sink::validate(&config.sinks, &config.topic)?;
+            Pipeline::validate(&config.pipeline)?;
+            config.validation.validate(&config.topic)?;
+            config.processing.validate()?;
+            config.circuit_breaker.validate()?;
+            config.sink_retry.validate()?;
+            if let Some(admin) = &config.admin {
+                admin.validate()?;
+            }
+            if config.database.is_none() && config.needs_database() {
+                return Err(
+                    "database is required by the postgres sink and the quarantine table".into(),
+                );
+            }
+            Ok(())
+        })?;
         info!("ConsumerConfig loaded: {:?}", config);
         Ok(config)
     }
+
+    pub fn uses_postgres(&self) -> bool {
+        self.sinks
+            .iter()
+            .any(|s| matches!(s, SinkSettings::Postgres))
+    }
+
+    /// Whether Postgres is used at all, so migrations and partitions must run.
+    pub fn needs_database(&self) -> bool {
+        self.uses_postgres() || self.validation.needs_database()
+    }
 }
 
 #[cfg(test)]
@@ -42,9 +131,72 @@ mod tests {
         assert_eq!(config.kafka_broker, "localhost:9092");
         assert_eq!(config.group_id, "consumer-group");
         assert_eq!(config.topic, "hello-topic");
-        assert_eq!(config.database.host, "localhost");
-        assert_eq!(config.database.port, 5432);
-        assert_eq!(config.database.pool_size, 8);
+        let database = config.database.as_ref().unwrap();
+        assert_eq!(database.host, "localhost");
+        assert_eq!(database.port, 5432);
+        assert_eq!(database.pool_size, 8);
+        assert!(config.run_migrations);
+        assert!(config.uses_postgres());
+    }
+
+    #[test]
+    fn database_is_optional_without_postgres_sink() {
+        let json = r#"{
+            "kafka_broker": "localhost:9092",
+            "group_id": "consumer-group",
+            "topic": "hello-topic",
+            "sinks": [{"type": "stdout"}]
+        }"#;
+        let config = ConsumerConfig::new(json).expect("config should parse");
+        assert!(config.database.is_none());
+        assert!(!config.uses_postgres());
+
+        let json = r#"{
+            "kafka_broker": "localhost:9092",
+            "group_id": "consumer-group",
+            "topic": "hello-topic"
+        }"#;
+        assert!(ConsumerConfig::new(json).is_err());
+
+        let json = r#"{
+            "kafka_broker": "localhost:9092",
+            "group_id": "consumer-group",
+            "topic": "hello-topic",
+            "sinks": [{"type": "stdout"}],
+            "validation": {"schemas": {"hello-topic": {"inline": {"type": "object"}}}}
+        }"#;
+        assert!(ConsumerConfig::new(json).is_err());
+    }
+
+    #[test]
+    fn parses_kafka_security_section() {
+        std::env::set_var("TERRARIUM_TEST_CONSUMER_SASL_PASSWORD", "secret");
+        let json = r#"{
+            "kafka_broker": "broker:9093",
+            "group_id": "consumer-group",
+            "topic": "hello-topic",
+            "database": {
+                "host": "localhost",
+                "port": 5432,
+                "user": "postgres",
+                "password": "postgres",
+                "dbname": "messages_db",
+                "pool_size": 8
+            },
+            "kafka_security": {
+                "protocol": "SASL_SSL",
+                "sasl": {
+                    "mechanism": "SCRAM-SHA-256",
+                    "username": "consumer",
+                    "password": { "env": "TERRARIUM_TEST_CONSUMER_SASL_PASSWORD" }
+                }
+            }
+        }"#;
+
+        let config = ConsumerConfig::new(json).expect("config should parse");
+        let props = config.kafka_security.properties().unwrap();
+        assert_eq!(props["security.protocol"], "SASL_SSL");
+        assert_eq!(props["sasl.mechanism"], "SCRAM-SHA-256");
     }
 
     #[test]
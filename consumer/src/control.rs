@@ -0,0 +1,415 @@
+use crate::breaker::{BreakerState, CircuitBreaker};
+use crate::inflight::InFlight;
+use crate::rebalance::KafkaConsumer;
+use chrono::{DateTime, Utc};
+use hyper::{body::to_bytes, Body, Method, Request as HttpRequest, Response as HttpResponse};
+use rdkafka::consumer::Consumer;
+use rdkafka::{Offset, TopicPartitionList};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use terrarium_core::admin::{self, AdminServer};
+
+/// How long calls that reach the brokers (committed offsets, seeks) may take.
+const KAFKA_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long a seek waits for the partition's messages being processed.
+const SEEK_DRAIN_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PartitionRequest {
+    /// Defaults to the consumer's `topic`.
+    topic: Option<String>,
+    partition: i32,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SeekRequest {
+    topic: Option<String>,
+    partition: i32,
+    offset: Option<i64>,
+    /// Seeks to the first message at or after this time.
+    timestamp: Option<DateTime<Utc>>,
+}
+
+type Failure = (u16, String);
+
+#[derive(Debug, Clone, Copy)]
+enum Action {
+    Partitions,
+    Pause,
+    Resume,
+    Seek,
+}
+
+fn offset_json(offset: Offset) -> Value {
+    match offset {
+        Offset::Offset(offset) => json!(offset),
+        Offset::Beginning => json!("beginning"),
+        Offset::End => json!("end"),
+        _ => Value::Null,
+    }
+}
+
+/// Operator control over the live consumer: pausing and resuming partitions,
+/// seeking, and reporting positions. Partitions are paused while an operator
+/// or the Postgres circuit breaker asks for it, and while they are backed up.
+pub struct PartitionControl {
+    consumer: Arc<KafkaConsumer>,
+    breaker: Arc<CircuitBreaker>,
+    in_flight: Arc<InFlight>,
+    default_topic: String,
+    paused: Mutex<HashSet<(String, i32)>>,
+    backlogged: Mutex<HashSet<(String, i32)>>,
+}
+
+impl PartitionControl {
+    pub fn new(
+        consumer: Arc<KafkaConsumer>,
+        breaker: Arc<CircuitBreaker>,
+        in_flight: Arc<InFlight>,
+        default_topic: &str,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            consumer,
+            breaker,
+            in_flight,
+            default_topic: default_topic.to_string(),
+            paused: Mutex::new(HashSet::new()),
+            backlogged: Mutex::new(HashSet::new()),
+        })
+    }
+
+    /// Replaces the set of partitions with too many messages in flight,
+    /// re-applying pauses when it changed.
+    pub fn set_backlogged(&self, partitions: HashSet<(String, i32)>) {
+        {
+            let mut backlogged = self.backlogged.lock().unwrap();
+            if *backlogged == partitions {
+                return;
+            }
+            *backlogged = partitions;
+        }
+        self.sync_pauses();
+    }
+
+    /// Pauses or resumes each assigned partition to match the breaker state,
+    /// operator pauses and backlog. Called after rebalances and breaker changes.
+    pub fn sync_pauses(&self) {
+        let assignment = match self.consumer.assignment() {
+            Ok(assignment) => assignment,
+            Err(e) => {
+                log::error!("Failed to read partition assignment: {}", e);
+                return;
+            }
+        };
+        let circuit_open = self.breaker.state() != BreakerState::Closed;
+        let operator_paused = self.paused.lock().unwrap();
+        let backlogged = self.backlogged.lock().unwrap();
+        let mut pause = TopicPartitionList::new();
+        let mut resume = TopicPartitionList::new();
+        for element in assignment.elements() {
+            let key = (element.topic().to_string(), element.partition());
+            if circuit_open || operator_paused.contains(&key) || backlogged.contains(&key) {
+                pause.add_partition(element.topic(), element.partition());
+            } else {
+                resume.add_partition(element.topic(), element.partition());
+            }
+        }
+        if pause.count() > 0 {
+            match self.consumer.pause(&pause) {
+                Ok(()) => log::debug!("Paused {} partition(s)", pause.count()),
+                Err(e) => log::error!("Failed to pause partitions: {}", e),
+            }
+        }
+        if resume.count() > 0 {
+            if let Err(e) = self.consumer.resume(&resume) {
+                log::error!("Failed to resume partitions: {}", e);
+            }
+        }
+    }
+
+    /// Registers the `/admin/*` endpoints, all requiring `token`.
+    pub fn routes(self: Arc<Self>, server: AdminServer, token: String) -> AdminServer {
+        let token: Arc<str> = token.into();
+        [
+            (Method::GET, "/admin/partitions", Action::Partitions),
+            (Method::POST, "/admin/pause", Action::Pause),
+            (Method::POST, "/admin/resume", Action::Resume),
+            (Method::POST, "/admin/seek", Action::Seek),
+        ]
+        .into_iter()
+        .fold(server, |server, (method, path, action)| {
+            let control = self.clone();
+            let token = token.clone();
+            server.route(method, path, move |req| {
+                control.clone().handle(token.clone(), action, req)
+            })
+        })
+    }
+
+    async fn handle(
+        self: Arc<Self>,
+        token: Arc<str>,
+        action: Action,
+        req: HttpRequest<Body>,
+    ) -> HttpResponse<Body> {
+        if !admin::bearer_authorized(&req, &token) {
+            return admin::text(401, "unauthorized");
+        }
+        let body = match to_bytes(req.into_body()).await {
+            Ok(body) => body,
+            Err(e) => return admin::text(400, format!("failed to read body: {}", e)),
+        };
+        // Broker round trips block, so they run off the async workers.
+        let result = tokio::task::spawn_blocking(move || match action {
+            Action::Partitions => self.partitions(),
+            Action::Pause => self.set_paused(parse(&body)?, true),
+            Action::Resume => self.set_paused(parse(&body)?, false),
+            Action::Seek => self.seek(parse(&body)?),
+        })
+        .await
+        .unwrap_or_else(|e| Err((500, format!("admin task failed: {}", e))));
+        match result {
+            Ok(value) => admin::json(200, &value),
+            Err((status, message)) => admin::json(status, &json!({ "error": message })),
+        }
+    }
+
+    fn partitions(&self) -> Result<Value, Failure> {
+        let kafka = |e: rdkafka::error::KafkaError| (502, e.to_string());
+        let assignment = self.consumer.assignment().map_err(kafka)?;
+        let position = self.consumer.position().map_err(kafka)?;
+        let committed = if assignment.count() == 0 {
+            TopicPartitionList::new()
+        } else {
+            self.consumer
+                .committed_offsets(assignment.clone(), KAFKA_TIMEOUT)
+                .map_err(kafka)?
+        };
+        let operator_paused = self.paused.lock().unwrap();
+        let offset_of = |list: &TopicPartitionList, topic: &str, partition: i32| {
+            list.find_partition(topic, partition)
+                .map_or(Value::Null, |element| offset_json(element.offset()))
+        };
+        let partitions: Vec<Value> = assignment
+            .elements()
+            .iter()
+            .map(|element| {
+                let (topic, partition) = (element.topic(), element.partition());
+                json!({
+                    "topic": topic,
+                    "partition": partition,
+                    "position": offset_of(&position, topic, partition),
+                    "committed": offset_of(&committed, topic, partition),
+                    "paused_by_operator":
+                        operator_paused.contains(&(topic.to_string(), partition)),
+                })
+            })
+            .collect();
+        Ok(json!({
+            "partitions": partitions,
+            "circuit": self.breaker.state().as_str(),
+        }))
+    }
+
+    fn assigned(&self, topic: Option<String>, partition: i32) -> Result<String, Failure> {
+        let topic = topic.unwrap_or_else(|| self.default_topic.clone());
+        let assignment = self
+            .consumer
+            .assignment()
+            .map_err(|e| (502, e.to_string()))?;
+        if assignment.find_partition(&topic, partition).is_none() {
+            return Err((
+                409,
+                format!("{}[{}] is not assigned to this consumer", topic, partition),
+            ));
+        }
+        Ok(topic)
+    }
+
+    fn set_paused(&self, request: PartitionRequest, paused: bool) -> Result<Value, Failure> {
+        let topic = self.assigned(request.topic, request.partition)?;
+        let key = (topic.clone(), request.partition);
+        {
+            let mut operator_paused = self.paused.lock().unwrap();
+            if paused {
+                operator_paused.insert(key);
+            } else {
+                operator_paused.remove(&key);
+            }
+        }
+        log::info!(
+            "Operator {} {}[{}]",
+            if paused { "paused" } else { "resumed" },
+            topic,
+            request.partition
+        );
+        self.sync_pauses();
+        Ok(json!({
+            "topic": topic,
+            "partition": request.partition,
+            "paused_by_operator": paused,
+            "circuit": self.breaker.state().as_str(),
+        }))
+    }
+
+    /// Moves a partition to an offset or timestamp. Messages of the partition
+    /// that are still queued are dropped and the ones being processed are
+    /// waited for, so nothing from before the seek is committed after it.
+    fn seek(&self, request: SeekRequest) -> Result<Value, Failure> {
+        if request.offset.is_some() == request.timestamp.is_some() {
+            return Err((
+                400,
+                "exactly one of offset and timestamp is required".to_string(),
+            ));
+        }
+        let topic = self.assigned(request.topic, request.partition)?;
+        let offset = match (request.offset, request.timestamp) {
+            (Some(offset), _) => Offset::Offset(offset),
+            (_, Some(timestamp)) => {
+                let mut query = TopicPartitionList::new();
+                query
+                    .add_partition_offset(
+                        &topic,
+                        request.partition,
+                        Offset::Offset(timestamp.timestamp_millis()),
+                    )
+                    .map_err(|e| (400, e.to_string()))?;
+                let found = self
+                    .consumer
+                    .offsets_for_times(query, KAFKA_TIMEOUT)
+                    .map_err(|e| (502, e.to_string()))?;
+                found
+                    .find_partition(&topic, request.partition)
+                    .map_or(Offset::End, |element| element.offset())
+            }
+            (None, None) => unreachable!("checked above"),
+        };
+
+        // Stop fetching while the partition drains; `sync_pauses` restores
+        // whatever else wants it paused.
+        let mut partition = TopicPartitionList::new();
+        partition.add_partition(&topic, request.partition);
+        self.consumer
+            .pause(&partition)
+            .map_err(|e| (502, e.to_string()))?;
+        let key = [(topic.clone(), request.partition)];
+        let drained = tokio::runtime::Handle::current()
+            .block_on(self.in_flight.drain(&key, SEEK_DRAIN_TIMEOUT));
+        if !drained {
+            log::warn!(
+                "Messages of {}[{}] are still being processed; their offsets are ignored after the seek",
+                topic,
+                request.partition
+            );
+        }
+        // Also clears a partition stalled on a failed message.
+        self.in_flight.forget(&key);
+        let sought = self
+            .consumer
+            .seek(&topic, request.partition, offset, KAFKA_TIMEOUT);
+        self.sync_pauses();
+        sought.map_err(|e| (502, e.to_string()))?;
+        log::info!(
+            "Operator moved {}[{}] to offset {:?}",
+            topic,
+            request.partition,
+            offset
+        );
+        Ok(json!({
+            "topic": topic,
+            "partition": request.partition,
+            "offset": offset_json(offset),
+        }))
+    }
+}
+
+fn parse<T: DeserializeOwned>(body: &[u8]) -> Result<T, Failure> {
+    serde_json::from_slice(body).map_err(|e| (400, format!("invalid request: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::inflight::InFlight;
+    use crate::rebalance::RebalanceContext;
+    use prometheus::Registry;
+    use rdkafka::config::ClientConfig;
+
+    fn server() -> AdminServer {
+        let registry = Registry::new();
+        let in_flight = InFlight::new(10, &registry).unwrap();
+        let (context, _) = RebalanceContext::new(&registry, in_flight.clone()).unwrap();
+        let consumer: Arc<KafkaConsumer> = Arc::new(
+            ClientConfig::new()
+                .set("bootstrap.servers", "localhost:9")
+                .set("group.id", "control-test")
+                .create_with_context(context)
+                .unwrap(),
+        );
+        let breaker = CircuitBreaker::new(Default::default(), &registry).unwrap();
+        let control = PartitionControl::new(consumer, breaker, in_flight, "hello-topic");
+        control.routes(AdminServer::new("Test", registry), "s3cret".to_string())
+    }
+
+    fn request(method: Method, path: &str, token: Option<&str>, body: &str) -> HttpRequest<Body> {
+        let mut builder = HttpRequest::builder().method(method).uri(path);
+        if let Some(token) = token {
+            builder = builder.header("Authorization", format!("Bearer {}", token));
+        }
+        builder.body(Body::from(body.to_string())).unwrap()
+    }
+
+    async fn body_json(resp: HttpResponse<Body>) -> Value {
+        serde_json::from_slice(&to_bytes(resp.into_body()).await.unwrap()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn requires_bearer_token() {
+        let server = server();
+        for token in [None, Some("wrong")] {
+            let req = request(Method::GET, "/admin/partitions", token, "");
+            assert_eq!(server.handle(req).await.unwrap().status(), 401);
+        }
+    }
+
+    #[tokio::test]
+    async fn reports_empty_assignment() {
+        let req = request(Method::GET, "/admin/partitions", Some("s3cret"), "");
+        let resp = server().handle(req).await.unwrap();
+        assert_eq!(resp.status(), 200);
+        assert_eq!(
+            body_json(resp).await,
+            json!({"partitions": [], "circuit": "closed"})
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_and_unassigned_requests() {
+        let server = server();
+        let cases = [
+            (
+                "/admin/seek",
+                r#"{"partition": 0, "offset": 1, "timestamp": "2026-01-01T00:00:00Z"}"#,
+                400,
+            ),
+            ("/admin/seek", r#"{"partition": 0}"#, 400),
+            ("/admin/seek", r#"{"partition": 0, "offset": 1}"#, 409),
+            ("/admin/seek", r#"{"partition": 0, "whence": 1}"#, 400),
+            ("/admin/pause", "not json", 400),
+            ("/admin/resume", r#"{"partition": 3}"#, 409),
+        ];
+        for (path, body, status) in cases {
+            let req = request(Method::POST, path, Some("s3cret"), body);
+            let resp = server.handle(req).await.unwrap();
+            assert_eq!(resp.status(), status, "{} {}", path, body);
+            assert!(body_json(resp).await["error"].is_string());
+        }
+    }
+}
@@ -0,0 +1,328 @@
+use crate::breaker::CircuitBreaker;
+use chrono::Utc;
+use hyper::{Body, Method, Response as HttpResponse};
+use prometheus::proto::{MetricFamily, MetricType};
+use prometheus::Registry;
+use serde_json::{json, Value};
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use terrarium_core::admin::{self, AdminServer};
+use tokio::sync::watch;
+
+const DASHBOARD_HTML: &str = include_str!("../ui/dashboard.html");
+
+/// How often the registry is sampled and an update is pushed to clients.
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+/// Samples kept for the failure ratio and latency percentiles.
+const WINDOW: usize = 60;
+
+/// One reading of the metrics the dashboard charts.
+#[derive(Debug, Clone, Default)]
+struct Sample {
+    at: Option<Instant>,
+    consumed: f64,
+    /// Postgres insert failures.
+    failures: f64,
+    /// Records at least one sink failed to write.
+    unwritten: f64,
+    /// Cumulative `(upper bound, count)` pairs of the end-to-end latency histogram.
+    latency_buckets: Vec<(f64, u64)>,
+    assigned: i64,
+    /// `(topic, partition, lag)` of each assigned partition.
+    lag: Vec<(String, i32, i64)>,
+}
+
+fn family<'a>(families: &'a [MetricFamily], name: &str) -> Option<&'a MetricFamily> {
+    families.iter().find(|family| family.get_name() == name)
+}
+
+/// Value of an unlabelled counter or gauge, or 0 if it isn't registered.
+fn scalar(families: &[MetricFamily], name: &str) -> f64 {
+    family(families, name)
+        .and_then(|family| family.get_metric().first().map(|m| (family, m)))
+        .map_or(0.0, |(family, metric)| match family.get_field_type() {
+            MetricType::COUNTER => metric.get_counter().get_value(),
+            _ => metric.get_gauge().get_value(),
+        })
+}
+
+impl Sample {
+    fn take(registry: &Registry) -> Self {
+        let families = registry.gather();
+        let latency_buckets = family(&families, "consumer_end_to_end_latency_seconds")
+            .and_then(|family| family.get_metric().first())
+            .map(|metric| {
+                metric
+                    .get_histogram()
+                    .get_bucket()
+                    .iter()
+                    .map(|b| (b.get_upper_bound(), b.get_cumulative_count()))
+                    .collect()
+            })
+            .unwrap_or_default();
+        let lag = family(&families, "consumer_partition_lag")
+            .map(|family| {
+                family
+                    .get_metric()
+                    .iter()
+                    .filter_map(|metric| {
+                        let label = |name: &str| {
+                            metric
+                                .get_label()
+                                .iter()
+                                .find(|l| l.get_name() == name)
+                                .map(|l| l.get_value().to_string())
+                        };
+                        let partition = label("partition")?.parse().ok()?;
+                        Some((
+                            label("topic")?,
+                            partition,
+                            metric.get_gauge().get_value() as i64,
+                        ))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        Self {
+            at: Some(Instant::now()),
+            consumed: scalar(&families, "consumer_messages_total"),
+            failures: scalar(&families, "consumer_db_insert_failures_total"),
+            unwritten: scalar(&families, "consumer_messages_unwritten_total"),
+            latency_buckets,
+            assigned: scalar(&families, "consumer_assigned_partitions") as i64,
+            lag,
+        }
+    }
+}
+
+/// Estimates the `q` quantile from cumulative histogram buckets the way
+/// Prometheus' `histogram_quantile` does, interpolating within a bucket.
+fn quantile(buckets: &[(f64, u64)], q: f64) -> Option<f64> {
+    let total = buckets.last()?.1;
+    if total == 0 {
+        return None;
+    }
+    let rank = q * total as f64;
+    let mut lower = (0.0, 0u64);
+    for &(bound, count) in buckets {
+        if count as f64 >= rank {
+            if bound.is_infinite() {
+                // Beyond the largest finite bucket; report its bound.
+                return Some(lower.0);
+            }
+            let in_bucket = (count - lower.1) as f64;
+            let fraction = if in_bucket == 0.0 {
+                1.0
+            } else {
+                (rank - lower.1 as f64) / in_bucket
+            };
+            return Some(lower.0 + (bound - lower.0) * fraction);
+        }
+        lower = (bound, count);
+    }
+    Some(lower.0)
+}
+
+/// The per-bucket increase from `old` to `new`, as cumulative counts.
+fn bucket_delta(old: &[(f64, u64)], new: &[(f64, u64)]) -> Vec<(f64, u64)> {
+    new.iter()
+        .enumerate()
+        .map(|(i, &(bound, count))| {
+            let before = old.get(i).map_or(0, |&(_, c)| c);
+            (bound, count.saturating_sub(before))
+        })
+        .collect()
+}
+
+fn per_second(delta: f64, elapsed: Duration) -> f64 {
+    if elapsed.is_zero() {
+        0.0
+    } else {
+        delta / elapsed.as_secs_f64()
+    }
+}
+
+/// The update pushed to dashboard clients. Rates cover the last sample
+/// interval; the failure ratio and percentiles cover the whole window.
+fn summarize(window: &VecDeque<Sample>, circuit: &str) -> Value {
+    let (Some(first), Some(previous), Some(last)) = (
+        window.front(),
+        window.iter().rev().nth(1).or(window.back()),
+        window.back(),
+    ) else {
+        return Value::Null;
+    };
+    let elapsed = match (previous.at, last.at) {
+        (Some(from), Some(to)) => to.duration_since(from),
+        _ => Duration::ZERO,
+    };
+    let consumed = last.consumed - first.consumed;
+    let failures = last.failures - first.failures;
+    let latency = bucket_delta(&first.latency_buckets, &last.latency_buckets);
+    let window_secs = match (first.at, last.at) {
+        (Some(from), Some(to)) => to.duration_since(from).as_secs(),
+        _ => 0,
+    };
+    json!({
+        "time": Utc::now().to_rfc3339(),
+        "throughput": per_second(last.consumed - previous.consumed, elapsed),
+        "failure_rate": per_second(last.failures - previous.failures, elapsed),
+        "unwritten_rate": per_second(last.unwritten - previous.unwritten, elapsed),
+        "window_secs": window_secs,
+        "failure_ratio": (consumed > 0.0).then(|| failures / consumed),
+        "latency": {
+            "p50": quantile(&latency, 0.5),
+            "p95": quantile(&latency, 0.95),
+            "p99": quantile(&latency, 0.99),
+            "count": latency.last().map_or(0, |&(_, count)| count),
+        },
+        "assigned_partitions": last.assigned,
+        "total_lag": last.lag.iter().map(|(_, _, lag)| lag).sum::<i64>(),
+        "partitions": last
+            .lag
+            .iter()
+            .map(|(topic, partition, lag)| {
+                json!({"topic": topic, "partition": partition, "lag": lag})
+            })
+            .collect::<Vec<_>>(),
+        "circuit": circuit,
+    })
+}
+
+/// Live dashboard at `/dashboard`. A background task samples the metrics
+/// registry every second and streams a summary to each page over server-sent
+/// events at `/dashboard/events`.
+pub struct Dashboard {
+    updates: watch::Receiver<Arc<str>>,
+}
+
+impl Dashboard {
+    pub fn spawn(registry: Registry, breaker: Arc<CircuitBreaker>) -> Self {
+        let (sender, updates) = watch::channel(Arc::from("null"));
+        tokio::spawn(async move {
+            let mut window = VecDeque::with_capacity(WINDOW + 1);
+            let mut ticker = tokio::time::interval(SAMPLE_INTERVAL);
+            loop {
+                ticker.tick().await;
+                window.push_back(Sample::take(&registry));
+                if window.len() > WINDOW {
+                    window.pop_front();
+                }
+                let update = summarize(&window, breaker.state().as_str()).to_string();
+                if sender.send(Arc::from(update)).is_err() {
+                    return;
+                }
+            }
+        });
+        Self { updates }
+    }
+
+    /// Registers `/dashboard`, replacing the built-in page, and its event stream.
+    pub fn routes(self, server: AdminServer) -> AdminServer {
+        let updates = self.updates;
+        server
+            .route(Method::GET, "/dashboard", |_| async {
+                admin::html(DASHBOARD_HTML)
+            })
+            .route(Method::GET, "/dashboard/events", move |_| {
+                let updates = updates.clone();
+                async move { event_stream(updates) }
+            })
+    }
+}
+
+/// Sends the latest update right away, then each new one as it is sampled.
+fn event_stream(mut updates: watch::Receiver<Arc<str>>) -> HttpResponse<Body> {
+    updates.mark_changed();
+    let events = futures::stream::unfold(updates, |mut updates| async move {
+        updates.changed().await.ok()?;
+        let event = format!("data: {}\n\n", &**updates.borrow_and_update());
+        Some((Ok::<_, std::convert::Infallible>(event), updates))
+    });
+    HttpResponse::builder()
+        .status(200)
+        .header("Content-Type", "text/event-stream")
+        .header("Cache-Control", "no-cache")
+        .body(Body::wrap_stream(events))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+    use prometheus::{Histogram, HistogramOpts, IntCounter};
+    use terrarium_core::metrics;
+
+    #[test]
+    fn estimates_quantiles_like_prometheus() {
+        let buckets = [(0.1, 50), (0.5, 90), (1.0, 100), (f64::INFINITY, 100)];
+        assert_eq!(quantile(&buckets, 0.5), Some(0.1));
+        assert!((quantile(&buckets, 0.7).unwrap() - 0.3).abs() < 1e-9);
+        assert!((quantile(&buckets, 0.95).unwrap() - 0.75).abs() < 1e-9);
+        assert_eq!(quantile(&[(0.1, 0), (f64::INFINITY, 0)], 0.5), None);
+        assert_eq!(quantile(&[(0.1, 1), (f64::INFINITY, 4)], 0.99), Some(0.1));
+    }
+
+    #[test]
+    fn summarizes_rates_and_latency_over_the_window() {
+        let registry = Registry::new();
+        let consumed: IntCounter =
+            metrics::register_int_counter(&registry, "consumer_messages_total", "c").unwrap();
+        let failures: IntCounter =
+            metrics::register_int_counter(&registry, "consumer_db_insert_failures_total", "f")
+                .unwrap();
+        let unwritten: IntCounter =
+            metrics::register_int_counter(&registry, "consumer_messages_unwritten_total", "u")
+                .unwrap();
+        let latency = Histogram::with_opts(
+            HistogramOpts::new("consumer_end_to_end_latency_seconds", "l").buckets(vec![0.1, 1.0]),
+        )
+        .unwrap();
+        registry.register(Box::new(latency.clone())).unwrap();
+        // Observations before the window opens don't count towards it.
+        latency.observe(5.0);
+
+        let mut window = VecDeque::new();
+        window.push_back(Sample::take(&registry));
+        consumed.inc_by(10);
+        failures.inc();
+        unwritten.inc_by(2);
+        for _ in 0..9 {
+            latency.observe(0.05);
+        }
+        latency.observe(0.5);
+        let mut sample = Sample::take(&registry);
+        sample.at = window[0].at.map(|at| at + Duration::from_secs(2));
+        window.push_back(sample);
+
+        let update = summarize(&window, "closed");
+        assert_eq!(update["throughput"], 5.0);
+        assert_eq!(update["failure_rate"], 0.5);
+        assert_eq!(update["unwritten_rate"], 1.0);
+        assert_eq!(update["failure_ratio"], 0.1);
+        assert_eq!(update["latency"]["count"], 10);
+        assert!(update["latency"]["p50"].as_f64().unwrap() <= 0.1);
+        assert!(update["latency"]["p99"].as_f64().unwrap() > 0.1);
+        assert_eq!(update["partitions"], json!([]));
+        assert_eq!(update["circuit"], "closed");
+    }
+
+    #[tokio::test]
+    async fn streams_updates_as_server_sent_events() {
+        let (sender, updates) = watch::channel(Arc::from(r#"{"n":1}"#));
+        let resp = event_stream(updates);
+        assert_eq!(resp.headers()["Content-Type"], "text/event-stream");
+        let mut body = resp.into_body();
+        assert_eq!(
+            &body.next().await.unwrap().unwrap()[..],
+            b"data: {\"n\":1}\n\n"
+        );
+        sender.send(Arc::from(r#"{"n":2}"#)).unwrap();
+        assert_eq!(
+            &body.next().await.unwrap().unwrap()[..],
+            b"data: {\"n\":2}\n\n"
+        );
+    }
+}
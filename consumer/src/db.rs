@@ -1,32 +1,57 @@
-pub use deadpool_postgres::{Config, ManagerConfig, Pool, RecyclingMethod, Runtime};
-use serde::Deserialize;
+use crate::record::MessageRecord;
+use crate::validation::Rejection;
+pub use deadpool_postgres::{Config, ManagerConfig, Pool, PoolError, RecyclingMethod, Runtime};
+use openssl::ssl::{SslConnector, SslMethod, SslVerifyMode};
+use postgres_openssl::MakeTlsConnector;
+pub use terrarium_core::database::{DatabaseSettings, SslMode};
 use tokio_postgres::NoTls;
 
-#[derive(Debug, Deserialize)]
-pub struct DatabaseSettings {
-    pub host: String,
-    pub port: u16,
-    pub user: String,
-    pub password: String,
-    pub dbname: String,
-    pub pool_size: usize,
+fn tls_connector(
+    settings: &DatabaseSettings,
+) -> Result<MakeTlsConnector, Box<dyn std::error::Error>> {
+    let mut builder = SslConnector::builder(SslMethod::tls())?;
+    if let Some(root_cert) = &settings.ssl_root_cert {
+        builder.set_ca_file(root_cert)?;
+    }
+
+    let verify_hostname = settings.sslmode == Some(SslMode::VerifyFull);
+    if !verify_hostname && settings.ssl_root_cert.is_none() {
+        builder.set_verify(SslVerifyMode::NONE);
+    }
+
+    let mut connector = MakeTlsConnector::new(builder.build());
+    if !verify_hostname {
+        connector.set_callback(|config, _| {
+            config.set_verify_hostname(false);
+            Ok(())
+        });
+    }
+    Ok(connector)
 }
 
 pub async fn create_pool(settings: &DatabaseSettings) -> Result<Pool, Box<dyn std::error::Error>> {
-    log::info!(
-        "Creating database pool with host={}, port={}, dbname={}, user={}",
-        settings.host,
-        settings.port,
-        settings.dbname,
-        settings.user
-    );
-
     let mut cfg = Config::new();
-    cfg.host = Some(settings.host.clone());
-    cfg.port = Some(settings.port);
-    cfg.user = Some(settings.user.clone());
-    cfg.password = Some(settings.password.clone());
-    cfg.dbname = Some(settings.dbname.clone());
+    if let Some(url) = &settings.url {
+        log::info!(
+            "Creating database pool from connection URL with sslmode={:?}",
+            settings.sslmode
+        );
+        cfg.url = Some(url.clone());
+    } else {
+        log::info!(
+            "Creating database pool with host={}, port={}, dbname={}, user={}, sslmode={:?}",
+            settings.host,
+            settings.port,
+            settings.dbname,
+            settings.user,
+            settings.sslmode
+        );
+        cfg.host = Some(settings.host.clone());
+        cfg.port = Some(settings.port);
+        cfg.user = Some(settings.user.clone());
+        cfg.password = Some(settings.password.clone());
+        cfg.dbname = Some(settings.dbname.clone());
+    }
     cfg.manager = Some(ManagerConfig {
         recycling_method: RecyclingMethod::Fast,
     });
@@ -35,7 +60,13 @@ pub async fn create_pool(settings: &DatabaseSettings) -> Result<Pool, Box<dyn st
         ..Default::default()
     });
 
-    let pool = cfg.create_pool(Some(Runtime::Tokio1), NoTls)?;
+    let pool = match settings.sslmode {
+        None | Some(SslMode::Disable) => cfg.create_pool(Some(Runtime::Tokio1), NoTls)?,
+        Some(SslMode::Require | SslMode::VerifyFull) => {
+            cfg.ssl_mode = Some(deadpool_postgres::SslMode::Require);
+            cfg.create_pool(Some(Runtime::Tokio1), tls_connector(settings)?)?
+        }
+    };
 
     let client = pool.get().await?;
     let row = client.query_one("SELECT version()", &[]).await?;
@@ -48,26 +79,40 @@ pub async fn create_pool(settings: &DatabaseSettings) -> Result<Pool, Box<dyn st
 /// if the query fails, the transaction will automatically roll back
 pub async fn insert_message(
     pool: &Pool,
-    topic: &str,
-    partition: i32,
-    offset: i64,
-    payload: &str,
-) -> Result<(), Box<dyn std::error::Error>> {
+    record: &MessageRecord,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     log::debug!(
         "Attempting to insert message - Topic: {}, Partition: {}, Offset: {}",
-        topic,
-        partition,
-        offset
+        record.topic,
+        record.partition,
+        record.offset
     );
 
+    let (payload_json, payload_bytes) = record.payload.columns();
     let mut client = pool.get().await?;
     let tx = client.transaction().await?;
 
     // Execute the insert query.
     let rows = tx
         .execute(
-            "INSERT INTO messages (topic, part, kafkaoffset, payload) VALUES ($1, $2, $3, $4)",
-            &[&topic, &partition, &offset, &payload],
+            "INSERT INTO messages \
+             (topic, part, kafkaoffset, payload_json, payload_bytes, content_type, \
+              event_id, message_key, headers, kafka_timestamp, produced_at, consumed_at) \
+             VALUES ($1, $2, $3, $4::text::jsonb, $5, $6, $7, $8, $9, $10, $11, $12)",
+            &[
+                &record.topic,
+                &record.partition,
+                &record.offset,
+                &payload_json,
+                &payload_bytes,
+                &record.content_type,
+                &record.event_id(),
+                &record.key,
+                &record.headers,
+                &record.kafka_timestamp,
+                &record.produced_at(),
+                &record.consumed_at,
+            ],
         )
         .await
         .map_err(|e| {
@@ -80,3 +125,88 @@ pub async fn insert_message(
     log::debug!("Successfully inserted {} row(s) into messages table", rows);
     Ok(())
 }
+
+/// A cheap round trip, used to probe whether Postgres is reachable again.
+pub async fn ping(pool: &Pool) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    pool.get().await?.simple_query("SELECT 1").await?;
+    Ok(())
+}
+
+/// Whether `error` means Postgres could not be reached, as opposed to a problem
+/// with the statement or the row. Only these trip the circuit breaker.
+pub fn is_unavailable(error: &(dyn std::error::Error + 'static)) -> bool {
+    if let Some(error) = error.downcast_ref::<PoolError>() {
+        return match error {
+            PoolError::Backend(error) => is_connection_error(error),
+            _ => true,
+        };
+    }
+    error
+        .downcast_ref::<tokio_postgres::Error>()
+        .is_some_and(is_connection_error)
+}
+
+/// Connection failures and the SQLSTATE classes for connection exceptions (08),
+/// insufficient resources (53) and server shutdown (57P).
+fn is_connection_error(error: &tokio_postgres::Error) -> bool {
+    use std::error::Error;
+    if let Some(code) = error.code() {
+        return ["08", "53", "57P"]
+            .iter()
+            .any(|class| code.code().starts_with(class));
+    }
+    error.is_closed() || error.source().is_some_and(|e| e.is::<std::io::Error>())
+}
+
+pub async fn insert_quarantined(
+    pool: &Pool,
+    record: &MessageRecord,
+    rejection: &Rejection,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let client = pool.get().await?;
+    client
+        .execute(
+            "INSERT INTO quarantined_messages \
+             (topic, part, kafkaoffset, message_key, headers, payload, content_type, \
+              reason, errors, kafka_timestamp) \
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)",
+            &[
+                &record.topic,
+                &record.partition,
+                &record.offset,
+                &record.key,
+                &record.headers,
+                &record.payload.to_bytes(),
+                &record.content_type,
+                &rejection.reason,
+                &serde_json::json!(rejection.errors),
+                &record.kafka_timestamp,
+            ],
+        )
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(json: &str) -> DatabaseSettings {
+        serde_json::from_str(json).expect("database settings should parse")
+    }
+
+    #[test]
+    fn builds_tls_connector_for_require() {
+        let db =
+            parse(r#"{"url": "postgres://db/messages", "pool_size": 4, "sslmode": "require"}"#);
+        assert!(tls_connector(&db).is_ok());
+    }
+
+    #[test]
+    fn classifies_pool_errors_as_unavailable() {
+        let closed = PoolError::Closed;
+        assert!(is_unavailable(&closed));
+        let other: Box<dyn std::error::Error + Send + Sync> = "bad row".into();
+        assert!(!is_unavailable(other.as_ref()));
+    }
+}
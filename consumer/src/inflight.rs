@@ -0,0 +1,384 @@
+use crate::rebalance::KafkaConsumer;
+use prometheus::{IntGauge, Registry};
+use rdkafka::consumer::Consumer;
+use rdkafka::{Offset, TopicPartitionList};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use terrarium_core::metrics;
+use tokio::sync::Notify;
+
+pub type PartitionKey = (String, i32);
+
+/// Identifies which incarnation of a partition's queue a message was
+/// dispatched to; messages from a discarded queue are skipped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ticket {
+    generation: u64,
+    epoch: u64,
+}
+
+struct PartitionState {
+    /// Fixed when the partition is first seen after being forgotten.
+    generation: u64,
+    /// Advanced whenever queued messages are discarded.
+    epoch: u64,
+    /// Dispatched messages not yet processed or skipped.
+    queued: usize,
+    /// The offset after the last processed message.
+    next: Option<i64>,
+    /// Offsets a sink has accepted but not yet made durable, with a count each.
+    holds: BTreeMap<i64, usize>,
+    /// The first message that could not be processed. Nothing from here on is
+    /// committed or accepted until the partition is forgotten.
+    stalled_at: Option<i64>,
+    /// The offset last handed to librdkafka.
+    stored: Option<i64>,
+}
+
+impl PartitionState {
+    /// Where a new owner of the partition should start.
+    fn committable(&self) -> Option<i64> {
+        let next = self.next?;
+        [self.stalled_at, self.holds.keys().next().copied()]
+            .into_iter()
+            .flatten()
+            .chain([next])
+            .min()
+    }
+}
+
+#[derive(Default)]
+struct State {
+    partitions: HashMap<PartitionKey, PartitionState>,
+    /// Source of generations and epochs, so a ticket is never reused.
+    counter: u64,
+}
+
+impl State {
+    fn next_id(&mut self) -> u64 {
+        self.counter += 1;
+        self.counter
+    }
+
+    /// The partition's state if `ticket` belongs to its current generation.
+    fn current(&mut self, key: &PartitionKey, ticket: Ticket) -> Option<&mut PartitionState> {
+        self.partitions
+            .get_mut(key)
+            .filter(|partition| partition.generation == ticket.generation)
+    }
+}
+
+/// Per-partition bookkeeping for messages between the poll loop and a commit:
+/// how many are queued, which offsets are safe to commit, and which partitions
+/// are backed up and should be paused.
+pub struct InFlight {
+    limit: usize,
+    state: Mutex<State>,
+    /// Woken whenever a message is processed or skipped.
+    settled: Notify,
+    gauge: IntGauge,
+}
+
+impl InFlight {
+    /// A partition is backed up once `limit` of its messages are queued.
+    pub fn new(limit: usize, registry: &Registry) -> Result<Arc<Self>, prometheus::Error> {
+        Ok(Arc::new(Self {
+            limit,
+            state: Mutex::new(State::default()),
+            settled: Notify::new(),
+            gauge: metrics::register_int_gauge(
+                registry,
+                "consumer_in_flight_messages",
+                "Messages received from Kafka and not yet processed",
+            )?,
+        }))
+    }
+
+    /// Counts a message about to be queued and returns its ticket, or `None`
+    /// when the partition is stalled and the message should be dropped.
+    pub fn admit(&self, topic: &str, partition: i32) -> Option<Ticket> {
+        let mut state = self.state.lock().unwrap();
+        let key = (topic.to_string(), partition);
+        if !state.partitions.contains_key(&key) {
+            let id = state.next_id();
+            state.partitions.insert(
+                key.clone(),
+                PartitionState {
+                    generation: id,
+                    epoch: id,
+                    queued: 0,
+                    next: None,
+                    holds: BTreeMap::new(),
+                    stalled_at: None,
+                    stored: None,
+                },
+            );
+        }
+        let partition = state.partitions.get_mut(&key).expect("inserted above");
+        if partition.stalled_at.is_some() {
+            return None;
+        }
+        partition.queued += 1;
+        self.gauge.inc();
+        Some(Ticket {
+            generation: partition.generation,
+            epoch: partition.epoch,
+        })
+    }
+
+    /// Whether a worker should process a dequeued message. When not, the
+    /// message is counted as done.
+    pub fn start(&self, topic: &str, partition: i32, ticket: Ticket) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let key = (topic.to_string(), partition);
+        let current = state
+            .current(&key, ticket)
+            .is_some_and(|p| p.epoch == ticket.epoch && p.stalled_at.is_none());
+        if !current {
+            drop(state);
+            self.skip(topic, partition, ticket);
+        }
+        current
+    }
+
+    /// Counts a queued message as done without processing it.
+    pub fn skip(&self, topic: &str, partition: i32, ticket: Ticket) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(partition) = state.current(&(topic.to_string(), partition), ticket) {
+            partition.queued -= 1;
+        }
+        self.gauge.dec();
+        self.settled.notify_waiters();
+    }
+
+    /// Records the outcome of processing `offset`. A message that must not be
+    /// committed stalls its partition.
+    pub fn finish(
+        &self,
+        topic: &str,
+        partition: i32,
+        ticket: Ticket,
+        offset: i64,
+        committable: bool,
+    ) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(partition) = state.current(&(topic.to_string(), partition), ticket) {
+            partition.queued -= 1;
+            if committable {
+                partition.next = Some(offset + 1);
+            } else {
+                partition.stalled_at.get_or_insert(offset);
+            }
+        }
+        self.gauge.dec();
+        self.settled.notify_waiters();
+    }
+
+    /// Keeps `offset` from being committed until it is released; for sinks
+    /// that acknowledge a write before it is durable.
+    pub fn hold(&self, topic: &str, partition: i32, offset: i64) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(partition) = state.partitions.get_mut(&(topic.to_string(), partition)) {
+            *partition.holds.entry(offset).or_default() += 1;
+        }
+    }
+
+    pub fn release(&self, topic: &str, partition: i32, offset: i64) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(partition) = state.partitions.get_mut(&(topic.to_string(), partition)) {
+            if let Some(count) = partition.holds.get_mut(&offset) {
+                *count -= 1;
+                if *count == 0 {
+                    partition.holds.remove(&offset);
+                }
+            }
+        }
+    }
+
+    pub fn is_blocked(&self, topic: &str, partition: i32) -> bool {
+        let state = self.state.lock().unwrap();
+        state
+            .partitions
+            .get(&(topic.to_string(), partition))
+            .is_some_and(|p| self.blocks(p))
+    }
+
+    /// Partitions that should be paused: backed up or stalled.
+    pub fn blocked(&self) -> HashSet<PartitionKey> {
+        let state = self.state.lock().unwrap();
+        state
+            .partitions
+            .iter()
+            .filter(|(_, p)| self.blocks(p))
+            .map(|(key, _)| key.clone())
+            .collect()
+    }
+
+    fn blocks(&self, partition: &PartitionState) -> bool {
+        partition.queued >= self.limit || partition.stalled_at.is_some()
+    }
+
+    /// Discards the queued messages of `partitions` and waits up to `timeout`
+    /// for those being processed. Returns false if any were still running.
+    pub async fn drain(&self, partitions: &[PartitionKey], timeout: Duration) -> bool {
+        {
+            let mut state = self.state.lock().unwrap();
+            for key in partitions {
+                let epoch = state.next_id();
+                if let Some(partition) = state.partitions.get_mut(key) {
+                    partition.epoch = epoch;
+                }
+            }
+        }
+        let idle = async {
+            loop {
+                let settled = self.settled.notified();
+                let busy = {
+                    let state = self.state.lock().unwrap();
+                    partitions
+                        .iter()
+                        .any(|key| state.partitions.get(key).is_some_and(|p| p.queued > 0))
+                };
+                if !busy {
+                    return;
+                }
+                settled.await;
+            }
+        };
+        tokio::time::timeout(timeout, idle).await.is_ok()
+    }
+
+    /// Drops everything known about `partitions`, e.g. once they are revoked.
+    /// Messages still queued for them are skipped.
+    pub fn forget(&self, partitions: &[PartitionKey]) {
+        let mut state = self.state.lock().unwrap();
+        for key in partitions {
+            state.partitions.remove(key);
+        }
+    }
+
+    /// Stores the committable offset of every partition that advanced since
+    /// the last call, for the next (auto-)commit to pick up.
+    pub fn store(&self, consumer: &KafkaConsumer) {
+        let mut state = self.state.lock().unwrap();
+        let mut list = TopicPartitionList::new();
+        for ((topic, partition), p) in &state.partitions {
+            if let Some(offset) = p.committable().filter(|o| p.stored != Some(*o)) {
+                if let Err(e) = list.add_partition_offset(topic, *partition, Offset::Offset(offset))
+                {
+                    log::warn!("Failed to store offset for {}[{}]: {}", topic, partition, e);
+                }
+            }
+        }
+        if list.count() == 0 {
+            return;
+        }
+        if let Err(e) = consumer.store_offsets(&list) {
+            log::warn!("Failed to store offsets: {}", e);
+            return;
+        }
+        for element in list.elements() {
+            let key = (element.topic().to_string(), element.partition());
+            if let (Some(p), Offset::Offset(offset)) =
+                (state.partitions.get_mut(&key), element.offset())
+            {
+                p.stored = Some(offset);
+            }
+        }
+    }
+
+    #[cfg(test)]
+    pub fn committable(&self, topic: &str, partition: i32) -> Option<i64> {
+        let state = self.state.lock().unwrap();
+        state
+            .partitions
+            .get(&(topic.to_string(), partition))
+            .and_then(PartitionState::committable)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn in_flight(limit: usize) -> Arc<InFlight> {
+        InFlight::new(limit, &Registry::new()).unwrap()
+    }
+
+    fn process(in_flight: &InFlight, offset: i64, committable: bool) {
+        let ticket = in_flight.admit("t", 0).unwrap();
+        assert!(in_flight.start("t", 0, ticket));
+        in_flight.finish("t", 0, ticket, offset, committable);
+    }
+
+    #[test]
+    fn commits_up_to_the_first_unprocessed_message() {
+        let in_flight = in_flight(10);
+        assert_eq!(in_flight.committable("t", 0), None);
+        process(&in_flight, 4, true);
+        process(&in_flight, 5, true);
+        assert_eq!(in_flight.committable("t", 0), Some(6));
+        process(&in_flight, 6, false);
+        assert_eq!(in_flight.committable("t", 0), Some(6));
+    }
+
+    #[test]
+    fn commits_up_to_the_earliest_held_offset() {
+        let in_flight = in_flight(10);
+        process(&in_flight, 4, true);
+        in_flight.hold("t", 0, 5);
+        in_flight.hold("t", 0, 5);
+        process(&in_flight, 5, true);
+        process(&in_flight, 6, true);
+        assert_eq!(in_flight.committable("t", 0), Some(5));
+        in_flight.release("t", 0, 5);
+        assert_eq!(in_flight.committable("t", 0), Some(5));
+        in_flight.release("t", 0, 5);
+        assert_eq!(in_flight.committable("t", 0), Some(7));
+    }
+
+    #[test]
+    fn blocks_backed_up_and_stalled_partitions() {
+        let in_flight = in_flight(2);
+        let first = in_flight.admit("t", 0).unwrap();
+        in_flight.admit("t", 0).unwrap();
+        assert!(in_flight.is_blocked("t", 0));
+        assert!(!in_flight.is_blocked("t", 1));
+
+        assert!(in_flight.start("t", 0, first));
+        in_flight.finish("t", 0, first, 0, false);
+        assert!(in_flight.is_blocked("t", 0));
+        assert_eq!(in_flight.committable("t", 0), None);
+        // A stalled partition takes no more messages.
+        assert!(in_flight.admit("t", 0).is_none());
+        assert_eq!(in_flight.blocked(), HashSet::from([("t".to_string(), 0)]));
+
+        in_flight.forget(&[("t".to_string(), 0)]);
+        assert!(in_flight.blocked().is_empty());
+        assert!(in_flight.admit("t", 0).is_some());
+    }
+
+    #[tokio::test]
+    async fn drain_discards_queued_messages() {
+        let in_flight = in_flight(10);
+        let keys = [("t".to_string(), 0)];
+        let running = in_flight.admit("t", 0).unwrap();
+        let queued = in_flight.admit("t", 0).unwrap();
+        assert!(in_flight.start("t", 0, running));
+
+        let drain = in_flight.drain(&keys, Duration::from_secs(5));
+        let worker = async {
+            tokio::task::yield_now().await;
+            in_flight.finish("t", 0, running, 3, true);
+            assert!(!in_flight.start("t", 0, queued));
+        };
+        let (drained, ()) = tokio::join!(drain, worker);
+        assert!(drained);
+        // The message that was already running still counts.
+        assert_eq!(in_flight.committable("t", 0), Some(4));
+
+        in_flight.admit("t", 0).unwrap();
+        assert!(!in_flight.drain(&keys, Duration::from_millis(10)).await);
+    }
+}
@@ -7,29 +7,62 @@ use std::{
     time::Duration,
 };
 
-use chrono::{DateTime, Utc};
 use futures::stream::StreamExt;
-use hyper::service::{make_service_fn, service_fn};
-use hyper::{Body, Request as HttpRequest, Response as HttpResponse, Server as HttpServer};
-use prometheus::{Encoder, Histogram, HistogramOpts, IntCounter, Opts, Registry, TextEncoder};
+use hyper::Method;
+use prometheus::Registry;
 use rdkafka::config::ClientConfig;
-use rdkafka::consumer::{Consumer, StreamConsumer};
-use rdkafka::Message;
-use serde::Deserialize;
+use rdkafka::consumer::Consumer;
+use rdkafka::message::OwnedMessage;
+use terrarium_core::admin::{self, AdminServer};
+use terrarium_core::logging::{self, LogHandle};
+use terrarium_core::reload::{self, ConfigTracker, Live, ReloadFn};
+use tokio::sync::mpsc;
 use tokio::time::sleep;
 
+mod breaker;
 mod config;
+mod control;
+mod dashboard;
 mod db;
-
-#[derive(Debug, Deserialize)]
-struct HelloMessage {
-    name: String,
-    produced_at: DateTime<Utc>,
-}
+mod inflight;
+mod migrations;
+mod partitions;
+mod pipeline;
+mod processor;
+mod rebalance;
+mod record;
+mod sink;
+mod validation;
+mod workers;
+
+use breaker::{BreakerState, CircuitBreaker};
+use config::ConsumerConfig;
+use control::PartitionControl;
+use dashboard::Dashboard;
+use inflight::InFlight;
+use pipeline::Pipeline;
+use processor::Processor;
+use rebalance::{KafkaConsumer, RebalanceContext, RebalanceEvent};
+use sink::Sinks;
+use validation::{Quarantine, SchemaValidator};
+use workers::WorkerPool;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
-    env_logger::init();
+    let logs = logging::init();
+
+    // `consumer migrate` applies pending schema migrations and exits.
+    if std::env::args().nth(1).as_deref() == Some("migrate") {
+        let config = ConsumerConfig::load()?;
+        logs.set_filter(config.log_level.as_deref());
+        let database = config
+            .database
+            .as_ref()
+            .ok_or("migrate requires a database section in the config")?;
+        let db_pool = db::create_pool(database).await?;
+        migrations::run(&db_pool).await?;
+        return Ok(());
+    }
 
     // Set up graceful shutdown
     let running = Arc::new(AtomicBool::new(true));
@@ -41,215 +74,273 @@ async fn main() -> Result<(), Box<dyn Error>> {
 
     log::info!("Initializing consumer...");
 
-    let config_str = std::fs::read_to_string("config.json")?;
-    let config = config::ConsumerConfig::new(&config_str)?;
+    let config_str = ConsumerConfig::read_source()?;
+    let config = ConsumerConfig::new(&config_str)?;
+    logs.set_filter(config.log_level.as_deref());
 
     // Metrics registry and exporters
     let registry = Registry::new();
-    let messages_consumed = IntCounter::with_opts(Opts::new(
-        "consumer_messages_total",
-        "Total number of messages consumed from Kafka",
-    ))?;
-    let db_insert_failures = IntCounter::with_opts(Opts::new(
-        "consumer_db_insert_failures_total",
-        "Total number of DB insert failures",
-    ))?;
-    let end_to_end_latency = Histogram::with_opts(HistogramOpts::new(
-        "consumer_end_to_end_latency_seconds",
-        "End-to-end latency from API to DB sink",
-    ))?;
-
-    registry.register(Box::new(messages_consumed.clone()))?;
-    registry.register(Box::new(db_insert_failures.clone()))?;
-    registry.register(Box::new(end_to_end_latency.clone()))?;
-
-    // Spawn HTTP server for metrics and a simple dashboard
-    let http_registry = registry.clone();
-    let http_running = running.clone();
-    tokio::spawn(async move {
-        let make_svc = make_service_fn(move |_| {
-            let registry = http_registry.clone();
-            let running = http_running.clone();
-            async move {
-                Ok::<_, hyper::Error>(service_fn(move |req: HttpRequest<Body>| {
-                    let registry = registry.clone();
-                    let running = running.clone();
-                    async move { handle_http(req, registry, running).await }
-                }))
-            }
-        });
-
-        let addr = ([0, 0, 0, 0], 9100).into();
-        log::info!("Starting consumer metrics HTTP server on {}", addr);
-
-        if let Err(e) = HttpServer::bind(&addr).serve(make_svc).await {
-            log::error!("HTTP server error: {}", e);
+    let db_pool = match &config.database {
+        Some(database) if config.needs_database() => {
+            Some(prepare_database(database, &config).await?)
         }
-    });
-
-    let db_pool = db::create_pool(&config.database).await?;
+        _ => None,
+    };
+    let Components {
+        breaker,
+        in_flight,
+        context,
+        mut rebalances,
+        processor,
+        retry,
+        maintainer,
+    } = register_components(&config, db_pool.as_ref(), &registry)?;
+    if let Some(maintainer) = maintainer {
+        // Make sure today's partition exists before the first insert.
+        let report = maintainer.run_once().await?;
+        log::info!("Initial partition maintenance: {:?}", report);
+        tokio::spawn(maintainer.run());
+    }
+    let reload = config_reloader(&config_str, logs, retry)?;
+    tokio::spawn(reload::reload_on_hangup(reload.clone()));
 
-    let consumer: StreamConsumer = ClientConfig::new()
+    let mut client_config = ClientConfig::new();
+    client_config
         .set("bootstrap.servers", &config.kafka_broker)
         .set("group.id", &config.group_id)
         .set("enable.partition.eof", "false")
         .set("session.timeout.ms", "6000")
         .set("enable.auto.commit", "true")
+        // Offsets are stored from the poll loop once everything before them
+        // is processed, so auto-commit never covers messages still in flight.
+        .set("enable.auto.offset.store", "false")
         .set("auto.offset.reset", "earliest")
-        .create()?;
+        // Feeds the consumer_partition_lag gauge.
+        .set("statistics.interval.ms", "5000");
+    if let Some(strategy) = config.assignment_strategy {
+        client_config.set("partition.assignment.strategy", strategy.as_str());
+    }
+    for (key, value) in config.kafka_security.properties()? {
+        client_config.set(key, value);
+    }
+    let consumer: Arc<KafkaConsumer> = Arc::new(client_config.create_with_context(context)?);
+    consumer.context().attach(&consumer);
+    consumer.context().attach_processor(&processor);
+    // Created before the admin server so its endpoints can reach the consumer;
+    // the group is only joined once it subscribes below.
+    let control = PartitionControl::new(
+        consumer.clone(),
+        breaker.clone(),
+        in_flight.clone(),
+        &config.topic,
+    );
+
+    // Spawn HTTP server for metrics and the live dashboard
+    let dashboard = Dashboard::spawn(registry.clone(), breaker.clone());
+    let mut admin = dashboard.routes(admin_server(registry.clone(), breaker.clone()));
+    if let Some(settings) = &config.admin {
+        let token = settings.resolve_token()?;
+        admin = control.clone().routes(admin, token.clone());
+        admin = reload::route(admin, token, reload);
+    }
+    tokio::spawn(async move {
+        if let Err(e) = admin.serve(([0, 0, 0, 0], 9100).into()).await {
+            log::error!("HTTP server error: {}", e);
+        }
+    });
+
+    if let (Some(pool), true) = (&db_pool, config.uses_postgres()) {
+        let pool = pool.clone();
+        tokio::spawn(breaker.clone().run_probes(move || {
+            let pool = pool.clone();
+            async move { db::ping(&pool).await }
+        }));
+    }
+
     consumer.subscribe(&[&config.topic])?;
     log::info!("Listening to topic: {}", config.topic);
 
+    let workers = WorkerPool::spawn(&config.processing, in_flight.clone(), {
+        let processor = processor.clone();
+        move |message: OwnedMessage| {
+            let processor = processor.clone();
+            async move { processor.process(&message).await }
+        }
+    });
+
     let mut message_stream = consumer.stream();
+    let mut breaker_state = breaker.subscribe();
+    let mut housekeeping = tokio::time::interval(Duration::from_millis(100));
 
+    // Nothing in this loop waits on the workers, so rebalances, breaker
+    // changes and shutdown are handled while partitions are backed up.
     while running.load(Ordering::SeqCst) {
         tokio::select! {
             maybe_msg = message_stream.next() => {
-                if let Some(result) = maybe_msg {
-                    process_message(result, &db_pool, &messages_consumed, &db_insert_failures, &end_to_end_latency).await;
+                match maybe_msg {
+                    Some(Ok(message)) => {
+                        let backed_up = workers.dispatch(message.detach());
+                        if backed_up {
+                            control.set_backlogged(in_flight.blocked());
+                        }
+                    }
+                    Some(Err(e)) => log::error!("Error receiving message: {}", e),
+                    None => {}
                 }
             },
-            _ = sleep(Duration::from_millis(100)) => {}
+            Some(event) = rebalances.recv() => match event {
+                // Newly assigned partitions start paused if the circuit is
+                // open or an operator paused them.
+                RebalanceEvent::Assigned => control.sync_pauses(),
+                // Revoked partitions no longer count as backed up.
+                RebalanceEvent::Revoked => control.set_backlogged(in_flight.blocked()),
+            },
+            Ok(()) = breaker_state.changed() => {
+                breaker_state.borrow_and_update();
+                control.sync_pauses();
+            },
+            _ = housekeeping.tick() => {
+                in_flight.store(&consumer);
+                control.set_backlogged(in_flight.blocked());
+            }
         }
     }
 
     log::info!("Shutting down consumer...");
     drop(message_stream);
+    // Messages held by an open circuit are not waited for; their offsets were
+    // never stored, so they are redelivered after a restart.
+    tokio::select! {
+        _ = workers.shutdown() => {}
+        _ = breaker.wait_until_open() => {
+            log::warn!("Postgres unavailable; leaving in-flight messages for redelivery");
+        }
+    }
+    processor.flush().await;
+    consumer.context().commit_processed();
     drop(consumer);
     sleep(Duration::from_secs(1)).await;
     Ok(())
 }
 
-async fn process_message(
-    result: rdkafka::error::KafkaResult<rdkafka::message::BorrowedMessage<'_>>,
-    db_pool: &db::Pool,
-    messages_consumed: &IntCounter,
-    db_insert_failures: &IntCounter,
-    end_to_end_latency: &Histogram,
-) {
-    let message = match result {
-        Ok(msg) => msg,
-        Err(e) => {
-            log::error!("Error receiving message: {}", e);
-            return;
-        }
-    };
-
-    let payload = match message.payload() {
-        Some(p) => p,
-        None => return,
-    };
-
-    let payload_str = String::from_utf8_lossy(payload);
-    log::info!("Received message: {}", payload_str);
-
-    messages_consumed.inc();
+/// Settings applied by a reload; anything else is reported as needing a restart.
+const LIVE_SETTINGS: &[&str] = &["log_level", "sink_retry"];
+
+/// Re-reads the config on SIGHUP or `POST /admin/reload`.
+fn config_reloader(
+    config_str: &str,
+    logs: LogHandle,
+    retry: Arc<Live<sink::RetrySettings>>,
+) -> Result<ReloadFn, Box<dyn Error>> {
+    let tracker = ConfigTracker::new(config_str, LIVE_SETTINGS)?;
+    Ok(Arc::new(move || {
+        let config_str = ConsumerConfig::read_source().map_err(|e| e.to_string())?;
+        let config = ConsumerConfig::new(&config_str).map_err(|e| e.to_string())?;
+        let report = tracker.update(&config_str).map_err(|e| e.to_string())?;
+        logs.set_filter(config.log_level.as_deref());
+        retry.set(config.sink_retry);
+        Ok(report)
+    }))
+}
 
-    const MAX_RETRIES: usize = 3;
-    let mut inserted = false;
-    for attempt in 1..=MAX_RETRIES {
-        if db::insert_message(
-            db_pool,
-            message.topic(),
-            message.partition(),
-            message.offset(),
-            &payload_str,
-        )
-        .await
-        .is_ok()
-        {
-            log::info!(
-                "Successfully stored message in database - Topic: {}, Partition: {}, Offset: {}",
-                message.topic(),
-                message.partition(),
-                message.offset()
-            );
-            inserted = true;
-            break;
-        } else {
-            log::warn!(
-                "Failed to store message in database (attempt {}/{})",
-                attempt,
-                MAX_RETRIES
-            );
-            if attempt < MAX_RETRIES {
-                sleep(Duration::from_secs(1)).await;
-            }
-        }
-    }
-    if !inserted {
-        log::error!(
-            "Failed to store message in database after {} attempts",
-            MAX_RETRIES
-        );
-        db_insert_failures.inc();
-        return;
+/// Connects to Postgres and applies migrations.
+async fn prepare_database(
+    database: &db::DatabaseSettings,
+    config: &ConsumerConfig,
+) -> Result<db::Pool, Box<dyn Error>> {
+    let db_pool = db::create_pool(database).await?;
+    if config.run_migrations {
+        migrations::run(&db_pool).await?;
     }
+    Ok(db_pool)
+}
 
-    if let Ok(parsed) = serde_json::from_str::<HelloMessage>(&payload_str) {
-        let now = Utc::now();
-        let latency =
-            (now - parsed.produced_at).num_microseconds().unwrap_or(0) as f64 / 1_000_000.0;
-        if latency >= 0.0 {
-            end_to_end_latency.observe(latency);
-        }
-        log::info!(
-            "Processed JSON greeting for {} (produced_at: {}, end_to_end_latency_s: {:.6})",
-            parsed.name,
-            parsed.produced_at,
-            latency
-        );
+/// Everything that registers metrics, apart from the Kafka client itself.
+struct Components {
+    breaker: Arc<CircuitBreaker>,
+    in_flight: Arc<InFlight>,
+    context: RebalanceContext,
+    rebalances: mpsc::UnboundedReceiver<RebalanceEvent>,
+    processor: Arc<Processor>,
+    retry: Arc<Live<sink::RetrySettings>>,
+    /// Set when there is a database; not started yet.
+    maintainer: Option<partitions::PartitionMaintainer>,
+}
+
+/// Builds the consumer's components, registering their metrics in `registry`.
+/// Shared with the test that checks `local/` against the registry, so the two
+/// can't drift apart.
+fn register_components(
+    config: &ConsumerConfig,
+    db_pool: Option<&db::Pool>,
+    registry: &Registry,
+) -> Result<Components, Box<dyn Error>> {
+    let breaker = CircuitBreaker::new(config.circuit_breaker.clone(), registry)?;
+    let in_flight = InFlight::new(config.processing.max_in_flight_per_partition, registry)?;
+    let (context, rebalances) = RebalanceContext::new(registry, in_flight.clone())?;
+    let retry = Live::new(config.sink_retry.clone());
+    let sinks = Sinks::new(
+        config,
+        db_pool,
+        &breaker,
+        retry.clone(),
+        &in_flight,
+        registry,
+    )?;
+    let quarantine = if config.validation.schemas.is_empty() {
+        None
     } else {
-        log::info!("Processed plain text message: {}", payload_str);
-    }
+        Some(Quarantine::new(
+            &config.validation.quarantine,
+            db_pool,
+            &config.kafka_broker,
+            &config.kafka_security,
+        )?)
+    };
+    let processor = Arc::new(Processor::new(
+        SchemaValidator::new(&config.validation)?,
+        quarantine,
+        Pipeline::new(config.pipeline.clone()),
+        sinks,
+        registry,
+    )?);
+    let maintainer = db_pool
+        .map(|pool| {
+            partitions::PartitionMaintainer::new(pool.clone(), config.partitions.clone(), registry)
+        })
+        .transpose()?;
+    Ok(Components {
+        breaker,
+        in_flight,
+        context,
+        rebalances,
+        processor,
+        retry,
+        maintainer,
+    })
 }
 
-async fn handle_http(
-    req: HttpRequest<Body>,
-    registry: Registry,
-    _running: Arc<AtomicBool>,
-) -> Result<HttpResponse<Body>, hyper::Error> {
-    match (req.method(), req.uri().path()) {
-        (&hyper::Method::GET, "/metrics") => {
-            let encoder = TextEncoder::new();
-            let metric_families = registry.gather();
-            let mut buffer = Vec::new();
-            if let Err(e) = encoder.encode(&metric_families, &mut buffer) {
-                log::error!("Failed to encode metrics: {}", e);
-                return Ok(HttpResponse::builder()
-                    .status(500)
-                    .body(Body::from("failed to encode metrics"))
-                    .unwrap());
+fn admin_server(registry: Registry, breaker: Arc<CircuitBreaker>) -> AdminServer {
+    AdminServer::new("Consumer Dashboard", registry).route(Method::GET, "/healthz", move |_| {
+        let state = breaker.state();
+        async move {
+            match state {
+                BreakerState::Closed => admin::text(200, "ok"),
+                state => admin::text(503, format!("postgres circuit {}", state.as_str())),
             }
-            Ok(HttpResponse::builder()
-                .status(200)
-                .header("Content-Type", encoder.format_type())
-                .body(Body::from(buffer))
-                .unwrap())
-        }
-        (&hyper::Method::GET, "/healthz") => Ok(HttpResponse::new(Body::from("ok"))),
-        (&hyper::Method::GET, "/dashboard") => {
-            let body = "<html><head><title>Consumer Dashboard</title></head><body><h1>Consumer Dashboard</h1><p>Prometheus metrics are available at <a href=\"/metrics\">/metrics</a>.</p><p>Health check: <a href=\"/healthz\">/healthz</a></p></body></html>";
-            Ok(HttpResponse::builder()
-                .status(200)
-                .header("Content-Type", "text/html; charset=utf-8")
-                .body(Body::from(body))
-                .unwrap())
         }
-        _ => Ok(HttpResponse::builder()
-            .status(404)
-            .body(Body::from("not found"))
-            .unwrap()),
-    }
+    })
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use hyper::{body::to_bytes, Method};
+    use hyper::{body::to_bytes, Body, Request as HttpRequest};
 
-    fn running_flag() -> Arc<AtomicBool> {
-        Arc::new(AtomicBool::new(true))
+    fn test_admin_server() -> AdminServer {
+        let registry = Registry::new();
+        let breaker = CircuitBreaker::new(Default::default(), &registry).unwrap();
+        admin_server(registry, breaker)
     }
 
     #[tokio::test]
@@ -260,14 +351,31 @@ mod tests {
             .body(Body::empty())
             .unwrap();
 
-        let resp = handle_http(req, Registry::new(), running_flag())
-            .await
-            .unwrap();
+        let resp = test_admin_server().handle(req).await.unwrap();
         assert_eq!(resp.status(), 200);
         let bytes = to_bytes(resp.into_body()).await.unwrap();
         assert_eq!(&bytes[..], b"ok");
     }
 
+    #[tokio::test]
+    async fn healthz_reports_open_circuit() {
+        let registry = Registry::new();
+        let breaker = CircuitBreaker::new(Default::default(), &registry).unwrap();
+        for _ in 0..5 {
+            breaker.record_failure();
+        }
+        let req = HttpRequest::builder()
+            .method(Method::GET)
+            .uri("/healthz")
+            .body(Body::empty())
+            .unwrap();
+
+        let resp = admin_server(registry, breaker).handle(req).await.unwrap();
+        assert_eq!(resp.status(), 503);
+        let bytes = to_bytes(resp.into_body()).await.unwrap();
+        assert_eq!(&bytes[..], b"postgres circuit open");
+    }
+
     #[tokio::test]
     async fn metrics_endpoint_returns_prometheus_text() {
         let req = HttpRequest::builder()
@@ -276,9 +384,7 @@ mod tests {
             .body(Body::empty())
             .unwrap();
 
-        let resp = handle_http(req, Registry::new(), running_flag())
-            .await
-            .unwrap();
+        let resp = test_admin_server().handle(req).await.unwrap();
         assert_eq!(resp.status(), 200);
         assert!(resp.headers().get("Content-Type").is_some());
     }
@@ -291,9 +397,34 @@ mod tests {
             .body(Body::empty())
             .unwrap();
 
-        let resp = handle_http(req, Registry::new(), running_flag())
-            .await
-            .unwrap();
+        let resp = test_admin_server().handle(req).await.unwrap();
         assert_eq!(resp.status(), 404);
     }
+
+    #[tokio::test]
+    async fn monitoring_references_only_registered_metrics() {
+        let config = ConsumerConfig::new(
+            r#"{
+                "kafka_broker": "localhost:9",
+                "group_id": "consumer-group",
+                "topic": "hello-topic",
+                "sinks": [{"type": "stdout"}]
+            }"#,
+        )
+        .unwrap();
+        // A pool that never connects, so partition maintenance registers too.
+        let pool = deadpool_postgres::Config {
+            host: Some("localhost".to_string()),
+            dbname: Some("postgres".to_string()),
+            ..Default::default()
+        }
+        .create_pool(
+            Some(deadpool_postgres::Runtime::Tokio1),
+            tokio_postgres::NoTls,
+        )
+        .unwrap();
+        let registry = Registry::new();
+        register_components(&config, Some(&pool), &registry).unwrap();
+        terrarium_core::metrics::assert_referenced_registered(&registry, "consumer_");
+    }
 }
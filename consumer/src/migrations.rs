@@ -0,0 +1,49 @@
+use crate::db::Pool;
+
+/// Versioned SQL files from the repository-level `migrations/` directory. Applied
+/// versions and their checksums are tracked in `refinery_schema_history`, and a
+/// migration whose file changed after being applied aborts the run.
+mod embedded {
+    refinery::embed_migrations!("../migrations");
+}
+
+pub async fn run(pool: &Pool) -> Result<(), Box<dyn std::error::Error>> {
+    let mut client = pool.get().await?;
+    let report = embedded::migrations::runner()
+        .run_async(&mut **client)
+        .await?;
+
+    let applied = report.applied_migrations();
+    if applied.is_empty() {
+        log::info!("Database schema is up to date");
+    }
+    for migration in applied {
+        log::info!("Applied migration {}", migration);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::embedded;
+
+    #[test]
+    fn migrations_are_numbered_contiguously_from_one() {
+        let migrations = embedded::migrations::runner().get_migrations().clone();
+        let mut versions: Vec<u32> = migrations.iter().map(|m| m.version()).collect();
+        versions.sort_unstable();
+        let expected: Vec<u32> = (1..=versions.len() as u32).collect();
+        assert_eq!(versions, expected);
+    }
+
+    #[test]
+    fn first_migration_creates_messages_table() {
+        let migrations = embedded::migrations::runner().get_migrations().clone();
+        let first = migrations.iter().find(|m| m.version() == 1).unwrap();
+        assert_eq!(first.name(), "create_messages");
+        assert!(first
+            .sql()
+            .unwrap()
+            .contains("CREATE TABLE IF NOT EXISTS messages"));
+    }
+}
@@ -0,0 +1,261 @@
+use crate::db::Pool;
+use chrono::{DateTime, Days, NaiveDate, Utc};
+use prometheus::{IntCounter, Registry};
+use serde::Deserialize;
+use std::time::Duration;
+use terrarium_core::metrics;
+
+/// Daily partition maintenance for the `messages` table.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct PartitionSettings {
+    /// How often partitions are created and expired.
+    pub maintenance_interval_secs: u64,
+    /// Number of daily partitions kept ready ahead of today (including today).
+    pub premake_days: u32,
+    /// Partitions whose range ends more than this many days ago are dropped.
+    /// Data is kept forever when unset.
+    pub retention_days: Option<u32>,
+}
+
+impl Default for PartitionSettings {
+    fn default() -> Self {
+        Self {
+            maintenance_interval_secs: 3600,
+            premake_days: 7,
+            retention_days: None,
+        }
+    }
+}
+
+impl PartitionSettings {
+    pub fn validate(&self) -> Result<(), Box<dyn std::error::Error>> {
+        if self.maintenance_interval_secs == 0 {
+            return Err("partitions.maintenance_interval_secs must be greater than 0".into());
+        }
+        if self.premake_days < 2 {
+            return Err("partitions.premake_days must be at least 2".into());
+        }
+        if self.retention_days == Some(0) {
+            return Err("partitions.retention_days must be greater than 0".into());
+        }
+        Ok(())
+    }
+}
+
+/// Advisory lock key serializing maintenance across consumers ("msgparts").
+const MAINTENANCE_LOCK: i64 = 0x6d73_6770_6172_7473;
+
+fn partition_name(day: NaiveDate) -> String {
+    format!("messages_p{}", day.format("%Y%m%d"))
+}
+
+fn day_start(day: NaiveDate) -> DateTime<Utc> {
+    day.and_hms_opt(0, 0, 0).unwrap().and_utc()
+}
+
+/// Days that still need a partition so that `premake_days` days starting at
+/// `today` are covered, given where the newest existing partition ends.
+fn days_to_create(
+    latest_end: Option<NaiveDate>,
+    today: NaiveDate,
+    premake_days: u32,
+) -> Vec<NaiveDate> {
+    let horizon = today + Days::new(premake_days as u64);
+    let mut day = latest_end.map_or(today, |end| end.max(today));
+    let mut days = Vec::new();
+    while day < horizon {
+        days.push(day);
+        day = day + Days::new(1);
+    }
+    days
+}
+
+/// Partitions ending at or before this instant are past retention.
+fn retention_cutoff(today: NaiveDate, retention_days: u32) -> DateTime<Utc> {
+    day_start(today - Days::new(retention_days as u64))
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct MaintenanceReport {
+    pub partitions_created: u64,
+    pub partitions_dropped: u64,
+    pub rows_dropped: u64,
+}
+
+pub struct PartitionMaintainer {
+    pool: Pool,
+    settings: PartitionSettings,
+    partitions_created: IntCounter,
+    partitions_dropped: IntCounter,
+    rows_dropped: IntCounter,
+}
+
+impl PartitionMaintainer {
+    pub fn new(
+        pool: Pool,
+        settings: PartitionSettings,
+        registry: &Registry,
+    ) -> Result<Self, prometheus::Error> {
+        Ok(Self {
+            pool,
+            settings,
+            partitions_created: metrics::register_int_counter(
+                registry,
+                "consumer_partitions_created_total",
+                "Total number of messages partitions created",
+            )?,
+            partitions_dropped: metrics::register_int_counter(
+                registry,
+                "consumer_partitions_dropped_total",
+                "Total number of messages partitions dropped by retention",
+            )?,
+            rows_dropped: metrics::register_int_counter(
+                registry,
+                "consumer_partition_rows_dropped_total",
+                "Total number of message rows removed by dropping expired partitions",
+            )?,
+        })
+    }
+
+    /// Runs maintenance forever, starting after one interval. Call
+    /// [`run_once`](Self::run_once) first so today's partition exists before consuming.
+    pub async fn run(self) {
+        let mut interval =
+            tokio::time::interval(Duration::from_secs(self.settings.maintenance_interval_secs));
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            if let Err(e) = self.run_once().await {
+                log::error!("Partition maintenance failed: {}", e);
+            }
+        }
+    }
+
+    pub async fn run_once(&self) -> Result<MaintenanceReport, Box<dyn std::error::Error>> {
+        let today = Utc::now().date_naive();
+        let mut client = self.pool.get().await?;
+        let tx = client.transaction().await?;
+        // Consumers in a group often start together; the lock makes the others
+        // wait and then see the partitions the first one created.
+        tx.execute("SELECT pg_advisory_xact_lock($1)", &[&MAINTENANCE_LOCK])
+            .await?;
+
+        let latest_end: Option<DateTime<Utc>> = tx
+            .query_one("SELECT MAX(range_end) FROM message_partitions", &[])
+            .await?
+            .get(0);
+        let mut created = Vec::new();
+        for day in days_to_create(
+            latest_end.map(|e| e.date_naive()),
+            today,
+            self.settings.premake_days,
+        ) {
+            let name = partition_name(day);
+            let (start, end) = (day_start(day), day_start(day + Days::new(1)));
+            tx.batch_execute(&format!(
+                "CREATE TABLE {} PARTITION OF messages FOR VALUES FROM ('{}') TO ('{}')",
+                name,
+                start.to_rfc3339(),
+                end.to_rfc3339()
+            ))
+            .await?;
+            tx.execute(
+                "INSERT INTO message_partitions (name, range_start, range_end) VALUES ($1, $2, $3)",
+                &[&name, &start, &end],
+            )
+            .await?;
+            created.push((name, day));
+        }
+
+        let mut dropped = Vec::new();
+        if let Some(retention_days) = self.settings.retention_days {
+            let cutoff = retention_cutoff(today, retention_days);
+            let expired = tx
+                .query(
+                    "SELECT name FROM message_partitions WHERE range_end <= $1 ORDER BY range_end",
+                    &[&cutoff],
+                )
+                .await?;
+            for row in expired {
+                let name: String = row.get(0);
+                let rows: i64 = tx
+                    .query_one(&format!("SELECT COUNT(*) FROM {}", name), &[])
+                    .await?
+                    .get(0);
+                tx.batch_execute(&format!("DROP TABLE {}", name)).await?;
+                tx.execute("DELETE FROM message_partitions WHERE name = $1", &[&name])
+                    .await?;
+                dropped.push((name, rows));
+            }
+        }
+        tx.commit().await?;
+
+        let mut report = MaintenanceReport::default();
+        for (name, day) in created {
+            log::info!("Created partition {} for {}", name, day);
+            self.partitions_created.inc();
+            report.partitions_created += 1;
+        }
+        for (name, rows) in dropped {
+            log::info!("Dropped partition {} ({} rows) past retention", name, rows);
+            self.partitions_dropped.inc();
+            self.rows_dropped.inc_by(rows as u64);
+            report.partitions_dropped += 1;
+            report.rows_dropped += rows as u64;
+        }
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    #[test]
+    fn names_partitions_by_day() {
+        assert_eq!(partition_name(date("2024-03-09")), "messages_p20240309");
+    }
+
+    #[test]
+    fn creates_missing_days_up_to_horizon() {
+        let today = date("2024-03-09");
+        assert_eq!(
+            days_to_create(None, today, 3),
+            vec![date("2024-03-09"), date("2024-03-10"), date("2024-03-11")]
+        );
+        // The newest partition already covers today and tomorrow.
+        assert_eq!(
+            days_to_create(Some(date("2024-03-11")), today, 3),
+            vec![date("2024-03-11")]
+        );
+        // Nothing to do once the horizon is covered.
+        assert!(days_to_create(Some(date("2024-03-20")), today, 3).is_empty());
+        // A stale newest partition never causes past days to be created.
+        assert_eq!(
+            days_to_create(Some(date("2024-01-01")), today, 2),
+            vec![date("2024-03-09"), date("2024-03-10")]
+        );
+    }
+
+    #[test]
+    fn retention_cutoff_is_midnight_utc() {
+        assert_eq!(
+            retention_cutoff(date("2024-03-09"), 30).to_rfc3339(),
+            "2024-02-08T00:00:00+00:00"
+        );
+    }
+
+    #[test]
+    fn validates_settings() {
+        assert!(PartitionSettings::default().validate().is_ok());
+        let settings: PartitionSettings = serde_json::from_str(r#"{"premake_days": 1}"#).unwrap();
+        assert!(settings.validate().is_err());
+        let settings: PartitionSettings = serde_json::from_str(r#"{"retention_days": 0}"#).unwrap();
+        assert!(settings.validate().is_err());
+    }
+}
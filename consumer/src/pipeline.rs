@@ -0,0 +1,318 @@
+use crate::record::{MessageRecord, Payload};
+use serde::Deserialize;
+use serde_json::Value;
+
+/// One processing step, selected by `stage`. Stages run in order on JSON
+/// object payloads; other payloads pass through every stage except `filter`
+/// unchanged.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "stage", rename_all = "lowercase")]
+pub enum Stage {
+    /// Copies values found at JSON pointers (e.g. `/user/name`) into top-level
+    /// fields. With `replace`, the payload becomes just the extracted fields.
+    Extract {
+        fields: Vec<ExtractField>,
+        #[serde(default)]
+        replace: bool,
+    },
+    /// Keeps only records whose payload satisfies the predicate.
+    Filter {
+        path: String,
+        op: FilterOp,
+        #[serde(default)]
+        value: Option<Value>,
+    },
+    /// Renames a top-level field; a missing field is left alone.
+    Rename { from: String, to: String },
+    /// Sets a top-level field to a value computed from the record.
+    Compute { field: String, value: Computed },
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ExtractField {
+    pub path: String,
+    pub to: String,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FilterOp {
+    Eq,
+    Ne,
+    Gt,
+    Lt,
+    Exists,
+    Missing,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Computed {
+    ConsumedAt,
+    KafkaTimestamp,
+    /// Milliseconds between the API producing the event and the consumer
+    /// receiving it; `null` when the payload had no `produced_at`.
+    LatencyMs,
+    Topic,
+    Partition,
+    Offset,
+}
+
+fn validate_pointer(path: &str) -> Result<(), Box<dyn std::error::Error>> {
+    if !path.is_empty() && !path.starts_with('/') {
+        return Err(format!(
+            "pipeline: path {:?} must be a JSON pointer starting with /",
+            path
+        )
+        .into());
+    }
+    Ok(())
+}
+
+impl Stage {
+    fn validate(&self) -> Result<(), Box<dyn std::error::Error>> {
+        match self {
+            Stage::Extract { fields, .. } => {
+                if fields.is_empty() {
+                    return Err("pipeline: extract needs at least one field".into());
+                }
+                for field in fields {
+                    validate_pointer(&field.path)?;
+                }
+            }
+            Stage::Filter { path, op, value } => {
+                validate_pointer(path)?;
+                let needs_value = !matches!(op, FilterOp::Exists | FilterOp::Missing);
+                if needs_value != value.is_some() {
+                    return Err(format!(
+                        "pipeline: filter op {:?} {} a value",
+                        op,
+                        if needs_value {
+                            "requires"
+                        } else {
+                            "does not take"
+                        }
+                    )
+                    .into());
+                }
+                if matches!(op, FilterOp::Gt | FilterOp::Lt)
+                    && !value.as_ref().is_some_and(Value::is_number)
+                {
+                    return Err("pipeline: gt and lt filters need a numeric value".into());
+                }
+            }
+            Stage::Rename { from, to } => {
+                if from == to {
+                    return Err("pipeline: rename from and to must differ".into());
+                }
+            }
+            Stage::Compute { field, .. } => {
+                if field.is_empty() {
+                    return Err("pipeline: compute field is required".into());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns false when the record should be dropped.
+    fn apply(&self, record: &mut MessageRecord) -> bool {
+        if let Stage::Filter { path, op, value } = self {
+            let found = record.payload.as_json().and_then(|v| v.pointer(path));
+            return matches(found, *op, value.as_ref());
+        }
+
+        let computed = match self {
+            Stage::Compute { value, .. } => computed_value(record, *value),
+            _ => Value::Null,
+        };
+        let Payload::Json {
+            value: Value::Object(object),
+            text,
+        } = &mut record.payload
+        else {
+            return true;
+        };
+        // The original text no longer matches once a stage changes the value.
+        *text = None;
+        match self {
+            Stage::Extract { fields, replace } => {
+                let source = Value::Object(object.clone());
+                if *replace {
+                    object.clear();
+                }
+                for field in fields {
+                    if let Some(found) = source.pointer(&field.path) {
+                        object.insert(field.to.clone(), found.clone());
+                    }
+                }
+            }
+            Stage::Rename { from, to } => {
+                if let Some(value) = object.remove(from) {
+                    object.insert(to.clone(), value);
+                }
+            }
+            Stage::Compute { field, .. } => {
+                object.insert(field.clone(), computed);
+            }
+            Stage::Filter { .. } => unreachable!("handled above"),
+        }
+        true
+    }
+}
+
+fn matches(found: Option<&Value>, op: FilterOp, expected: Option<&Value>) -> bool {
+    let as_f64 = |v: Option<&Value>| v.and_then(Value::as_f64);
+    match op {
+        FilterOp::Exists => found.is_some(),
+        FilterOp::Missing => found.is_none(),
+        FilterOp::Eq => found == expected,
+        FilterOp::Ne => found != expected,
+        FilterOp::Gt => matches!((as_f64(found), as_f64(expected)), (Some(a), Some(b)) if a > b),
+        FilterOp::Lt => matches!((as_f64(found), as_f64(expected)), (Some(a), Some(b)) if a < b),
+    }
+}
+
+fn computed_value(record: &MessageRecord, computed: Computed) -> Value {
+    match computed {
+        Computed::ConsumedAt => Value::String(record.consumed_at.to_rfc3339()),
+        Computed::KafkaTimestamp => record
+            .kafka_timestamp
+            .map_or(Value::Null, |t| Value::String(t.to_rfc3339())),
+        Computed::LatencyMs => record.produced_at().map_or(Value::Null, |produced_at| {
+            Value::from((record.consumed_at - produced_at).num_milliseconds())
+        }),
+        Computed::Topic => Value::String(record.topic.clone()),
+        Computed::Partition => Value::from(record.partition),
+        Computed::Offset => Value::from(record.offset),
+    }
+}
+
+/// The configured stages, applied between decoding a Kafka record and writing
+/// it to the sinks.
+#[derive(Debug, Clone, Default)]
+pub struct Pipeline {
+    stages: Vec<Stage>,
+}
+
+impl Pipeline {
+    pub fn new(stages: Vec<Stage>) -> Self {
+        Self { stages }
+    }
+
+    pub fn validate(stages: &[Stage]) -> Result<(), Box<dyn std::error::Error>> {
+        stages.iter().try_for_each(Stage::validate)
+    }
+
+    /// Runs every stage in order. Returns `None` if a filter dropped the record.
+    pub fn run(&self, mut record: MessageRecord) -> Option<MessageRecord> {
+        for stage in &self.stages {
+            if !stage.apply(&mut record) {
+                return None;
+            }
+        }
+        Some(record)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::record::HelloMessage;
+    use chrono::{Duration, Utc};
+    use serde_json::json;
+
+    fn record(payload: Value) -> MessageRecord {
+        let consumed_at = Utc::now();
+        MessageRecord {
+            topic: "default-topic".to_string(),
+            partition: 1,
+            offset: 9,
+            key: None,
+            headers: json!([]),
+            kafka_timestamp: None,
+            payload: Payload::json(payload),
+            content_type: "application/json".to_string(),
+            hello: Some(HelloMessage {
+                event_id: None,
+                name: "Bob".to_string(),
+                produced_at: consumed_at - Duration::milliseconds(250),
+            }),
+            consumed_at,
+        }
+    }
+
+    fn pipeline(json: Value) -> Pipeline {
+        let stages: Vec<Stage> = serde_json::from_value(json).unwrap();
+        Pipeline::validate(&stages).unwrap();
+        Pipeline::new(stages)
+    }
+
+    #[test]
+    fn extracts_renames_and_computes_fields() {
+        let pipeline = pipeline(json!([
+            {"stage": "extract", "fields": [{"path": "/user/name", "to": "name"}], "replace": true},
+            {"stage": "rename", "from": "name", "to": "user_name"},
+            {"stage": "compute", "field": "latency_ms", "value": "latency_ms"},
+            {"stage": "compute", "field": "offset", "value": "offset"}
+        ]));
+
+        let out = pipeline
+            .run(record(json!({"user": {"name": "Bob"}, "noise": true})))
+            .unwrap();
+        assert_eq!(
+            out.payload,
+            Payload::json(json!({"user_name": "Bob", "latency_ms": 250, "offset": 9}))
+        );
+    }
+
+    #[test]
+    fn filters_by_predicate() {
+        let pipeline = pipeline(json!([
+            {"stage": "filter", "path": "/name", "op": "ne", "value": "spam"},
+            {"stage": "filter", "path": "/score", "op": "gt", "value": 10}
+        ]));
+
+        assert!(pipeline
+            .run(record(json!({"name": "Bob", "score": 11})))
+            .is_some());
+        assert!(pipeline
+            .run(record(json!({"name": "spam", "score": 11})))
+            .is_none());
+        assert!(pipeline
+            .run(record(json!({"name": "Bob", "score": 3})))
+            .is_none());
+        assert!(pipeline.run(record(json!({"name": "Bob"}))).is_none());
+    }
+
+    #[test]
+    fn passes_non_json_payloads_through() {
+        let pipeline = pipeline(json!([
+            {"stage": "rename", "from": "a", "to": "b"},
+            {"stage": "compute", "field": "topic", "value": "topic"}
+        ]));
+        let mut text = record(json!(null));
+        text.payload = Payload::Bytes(b"hello".to_vec());
+        assert_eq!(
+            pipeline.run(text).unwrap().payload,
+            Payload::Bytes(b"hello".to_vec())
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_stages() {
+        let invalid = [
+            json!([{"stage": "filter", "path": "/a", "op": "eq"}]),
+            json!([{"stage": "filter", "path": "/a", "op": "exists", "value": 1}]),
+            json!([{"stage": "filter", "path": "/a", "op": "gt", "value": "x"}]),
+            json!([{"stage": "filter", "path": "name", "op": "exists"}]),
+            json!([{"stage": "rename", "from": "a", "to": "a"}]),
+            json!([{"stage": "extract", "fields": []}]),
+        ];
+        for stages in invalid {
+            let stages: Vec<Stage> = serde_json::from_value(stages).unwrap();
+            assert!(Pipeline::validate(&stages).is_err());
+        }
+        assert!(serde_json::from_value::<Vec<Stage>>(json!([{"stage": "explode"}])).is_err());
+    }
+}
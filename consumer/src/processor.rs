@@ -0,0 +1,275 @@
+use crate::pipeline::Pipeline;
+use crate::record::MessageRecord;
+use crate::sink::{SinkError, Sinks};
+use crate::validation::{Quarantine, Rejection, SchemaValidator};
+use chrono::Utc;
+use prometheus::{Histogram, IntCounter, IntCounterVec, Registry};
+use rdkafka::message::{Message, OwnedMessage};
+use std::time::Duration;
+use terrarium_core::metrics;
+use tokio::time::sleep;
+
+/// Everything between a record arriving from Kafka and it landing in the
+/// sinks: decode, schema validation, the pipeline and the sink fan-out.
+pub struct Processor {
+    validator: SchemaValidator,
+    quarantine: Option<Quarantine>,
+    pipeline: Pipeline,
+    sinks: Sinks,
+    messages_consumed: IntCounter,
+    messages_unwritten: IntCounter,
+    messages_filtered: IntCounter,
+    messages_rejected: IntCounterVec,
+    quarantine_failures: IntCounter,
+    end_to_end_latency: Histogram,
+}
+
+impl Processor {
+    /// `quarantine` must be set when `validator` has any schemas.
+    pub fn new(
+        validator: SchemaValidator,
+        quarantine: Option<Quarantine>,
+        pipeline: Pipeline,
+        sinks: Sinks,
+        registry: &Registry,
+    ) -> Result<Self, prometheus::Error> {
+        Ok(Self {
+            validator,
+            quarantine,
+            pipeline,
+            sinks,
+            messages_consumed: metrics::register_int_counter(
+                registry,
+                "consumer_messages_total",
+                "Total number of messages consumed from Kafka",
+            )?,
+            messages_unwritten: metrics::register_int_counter(
+                registry,
+                "consumer_messages_unwritten_total",
+                "Total number of messages that at least one sink failed to write",
+            )?,
+            messages_filtered: metrics::register_int_counter(
+                registry,
+                "consumer_messages_filtered_total",
+                "Total number of messages dropped by pipeline filters",
+            )?,
+            messages_rejected: metrics::register_int_counter_vec(
+                registry,
+                "consumer_messages_rejected_total",
+                "Total number of messages that failed schema validation, by topic and reason",
+                &["topic", "reason"],
+            )?,
+            quarantine_failures: metrics::register_int_counter(
+                registry,
+                "consumer_quarantine_failures_total",
+                "Total number of rejected messages that could not be quarantined",
+            )?,
+            end_to_end_latency: metrics::register_histogram(
+                registry,
+                "consumer_end_to_end_latency_seconds",
+                "End-to-end latency from API to DB sink",
+            )?,
+        })
+    }
+
+    /// Returns false when the message must not be committed: a sink failed to
+    /// write it, or it was rejected and could not be quarantined.
+    pub async fn process(&self, message: &OwnedMessage) -> bool {
+        let record = match MessageRecord::from_kafka(message) {
+            Some(record) => record,
+            None => return true,
+        };
+        log::info!("Received message: {}", record.payload);
+
+        self.messages_consumed.inc();
+
+        if let Err(rejection) = self.validator.check(&record) {
+            log::warn!(
+                "Rejected message - Topic: {}, Partition: {}, Offset: {}, reason: {}, errors: {:?}",
+                record.topic,
+                record.partition,
+                record.offset,
+                rejection.reason,
+                rejection.errors
+            );
+            self.messages_rejected
+                .with_label_values(&[&record.topic, &rejection.reason])
+                .inc();
+            if let Err(e) = self.quarantine(&record, &rejection).await {
+                log::error!(
+                    "Failed to quarantine rejected message; stopping {}[{}] at offset {}: {}",
+                    record.topic,
+                    record.partition,
+                    record.offset,
+                    e
+                );
+                self.quarantine_failures.inc();
+                return false;
+            }
+            return true;
+        }
+
+        let record = match self.pipeline.run(record) {
+            Some(record) => record,
+            None => {
+                log::debug!(
+                    "Pipeline filtered message - Topic: {}, Partition: {}, Offset: {}",
+                    message.topic(),
+                    message.partition(),
+                    message.offset()
+                );
+                self.messages_filtered.inc();
+                return true;
+            }
+        };
+
+        if !self.sinks.write(&record).await {
+            log::error!(
+                "Failed to write message; stopping {}[{}] at offset {}",
+                record.topic,
+                record.partition,
+                record.offset
+            );
+            self.messages_unwritten.inc();
+            return false;
+        }
+        log::info!(
+            "Successfully stored message - Topic: {}, Partition: {}, Offset: {}",
+            record.topic,
+            record.partition,
+            record.offset
+        );
+
+        if let Some(parsed) = &record.hello {
+            let now = Utc::now();
+            let latency =
+                (now - parsed.produced_at).num_microseconds().unwrap_or(0) as f64 / 1_000_000.0;
+            if latency >= 0.0 {
+                self.end_to_end_latency.observe(latency);
+            }
+            log::info!(
+                "Processed JSON greeting for {} (produced_at: {}, end_to_end_latency_s: {:.6})",
+                parsed.name,
+                parsed.produced_at,
+                latency
+            );
+        } else {
+            log::info!("Processed plain text message: {}", record.payload);
+        }
+        true
+    }
+
+    /// Writes a rejected record to quarantine, retrying per `sink_retry`.
+    async fn quarantine(
+        &self,
+        record: &MessageRecord,
+        rejection: &Rejection,
+    ) -> Result<(), SinkError> {
+        let quarantine = self
+            .quarantine
+            .as_ref()
+            .expect("quarantine is configured whenever schemas are");
+        let retry = self.sinks.retry();
+        let mut attempt = 1;
+        loop {
+            match quarantine.write(record, rejection).await {
+                Ok(()) => return Ok(()),
+                Err(e) if attempt < retry.max_attempts => {
+                    log::warn!(
+                        "Failed to quarantine rejected message (attempt {}/{}): {}",
+                        attempt,
+                        retry.max_attempts,
+                        e
+                    );
+                    sleep(Duration::from_millis(retry.backoff_ms)).await;
+                    attempt += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    pub async fn flush(&self) {
+        self.sinks.flush().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::inflight::InFlight;
+    use crate::sink::{RetrySettings, Sink};
+    use crate::workers::{ProcessingSettings, WorkerPool};
+    use async_trait::async_trait;
+    use rdkafka::message::Timestamp;
+    use std::sync::Arc;
+    use terrarium_core::reload::Live;
+
+    /// Fails every write of the record at `offset`.
+    struct FailsAt {
+        offset: i64,
+    }
+
+    #[async_trait]
+    impl Sink for FailsAt {
+        fn name(&self) -> &str {
+            "fails-at"
+        }
+
+        async fn write(&self, record: &MessageRecord) -> Result<(), SinkError> {
+            if record.offset == self.offset {
+                return Err("disk full".into());
+            }
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn holds_the_offset_of_an_unwritten_message() {
+        let registry = Registry::new();
+        let sinks = Sinks::from_sinks(
+            vec![Box::new(FailsAt { offset: 2 })],
+            Live::new(RetrySettings {
+                max_attempts: 1,
+                backoff_ms: 0,
+            }),
+            &registry,
+        )
+        .unwrap();
+        let processor = Arc::new(
+            Processor::new(
+                SchemaValidator::new(&Default::default()).unwrap(),
+                None,
+                Pipeline::new(Vec::new()),
+                sinks,
+                &registry,
+            )
+            .unwrap(),
+        );
+        let in_flight = InFlight::new(10, &registry).unwrap();
+        let pool = WorkerPool::spawn(&ProcessingSettings::default(), in_flight.clone(), {
+            let processor = processor.clone();
+            move |message: OwnedMessage| {
+                let processor = processor.clone();
+                async move { processor.process(&message).await }
+            }
+        });
+        for offset in 0..5 {
+            pool.dispatch(OwnedMessage::new(
+                Some(b"hello".to_vec()),
+                None,
+                "default-topic".to_string(),
+                Timestamp::NotAvailable,
+                0,
+                offset,
+                None,
+            ));
+        }
+        pool.shutdown().await;
+
+        // Nothing from the failed message on is committed, and the partition stops.
+        assert_eq!(in_flight.committable("default-topic", 0), Some(2));
+        assert!(in_flight.is_blocked("default-topic", 0));
+        assert_eq!(processor.messages_unwritten.get(), 1);
+    }
+}
@@ -0,0 +1,365 @@
+use crate::inflight::{InFlight, PartitionKey};
+use crate::processor::Processor;
+use prometheus::{IntCounter, IntCounterVec, IntGauge, IntGaugeVec, Registry};
+use rdkafka::client::ClientContext;
+use rdkafka::consumer::{CommitMode, Consumer, ConsumerContext, Rebalance, StreamConsumer};
+use rdkafka::error::KafkaResult;
+use rdkafka::statistics::Statistics;
+use rdkafka::types::RDKafkaErrorCode;
+use rdkafka::TopicPartitionList;
+use serde::Deserialize;
+use std::sync::{Arc, OnceLock, Weak};
+use std::time::Duration;
+use terrarium_core::metrics;
+use tokio::runtime::{Handle, RuntimeFlavor};
+use tokio::sync::mpsc;
+
+pub type KafkaConsumer = StreamConsumer<RebalanceContext>;
+
+/// How long a revocation waits for messages of the revoked partitions that
+/// are being processed. Ones still running afterwards are redelivered.
+const REVOKE_DRAIN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Value of librdkafka's `partition.assignment.strategy`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AssignmentStrategy {
+    Range,
+    Roundrobin,
+    /// Incremental rebalancing: only the partitions that move are revoked, so
+    /// the rest keep being consumed during a rebalance.
+    CooperativeSticky,
+}
+
+impl AssignmentStrategy {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AssignmentStrategy::Range => "range",
+            AssignmentStrategy::Roundrobin => "roundrobin",
+            AssignmentStrategy::CooperativeSticky => "cooperative-sticky",
+        }
+    }
+}
+
+/// What the main loop has to act on after a rebalance. Sent from the
+/// rebalance callback, which cannot await.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RebalanceEvent {
+    Assigned,
+    Revoked,
+}
+
+/// Logs and meters rebalances. Before partitions are revoked, drops their
+/// queued messages, waits for the ones being processed, flushes the sinks and
+/// commits, so the next owner starts right after the last message that was
+/// fully written. Also exports per-partition lag from librdkafka's statistics.
+pub struct RebalanceContext {
+    consumer: OnceLock<Weak<KafkaConsumer>>,
+    processor: OnceLock<Arc<Processor>>,
+    in_flight: Arc<InFlight>,
+    events: mpsc::UnboundedSender<RebalanceEvent>,
+    rebalances: IntCounterVec,
+    assigned_partitions: IntGauge,
+    revoke_commit_failures: IntCounter,
+    partition_lag: IntGaugeVec,
+}
+
+impl RebalanceContext {
+    pub fn new(
+        registry: &Registry,
+        in_flight: Arc<InFlight>,
+    ) -> Result<(Self, mpsc::UnboundedReceiver<RebalanceEvent>), prometheus::Error> {
+        let (events, receiver) = mpsc::unbounded_channel();
+        let context = Self {
+            consumer: OnceLock::new(),
+            processor: OnceLock::new(),
+            in_flight,
+            events,
+            rebalances: metrics::register_int_counter_vec(
+                registry,
+                "consumer_rebalances_total",
+                "Total number of rebalance callbacks, by event (assign, revoke, error)",
+                &["event"],
+            )?,
+            assigned_partitions: metrics::register_int_gauge(
+                registry,
+                "consumer_assigned_partitions",
+                "Partitions currently assigned to this consumer",
+            )?,
+            revoke_commit_failures: metrics::register_int_counter(
+                registry,
+                "consumer_revoke_commit_failures_total",
+                "Total number of failed offset commits before partitions were revoked",
+            )?,
+            partition_lag: metrics::register_int_gauge_vec(
+                registry,
+                "consumer_partition_lag",
+                "Messages between the high watermark and the last processed offset, by assigned partition",
+                &["topic", "partition"],
+            )?,
+        };
+        Ok((context, receiver))
+    }
+
+    /// Lets the callbacks reach the consumer that owns this context. Must be
+    /// called right after the consumer is created.
+    pub fn attach(&self, consumer: &Arc<KafkaConsumer>) {
+        if self.consumer.set(Arc::downgrade(consumer)).is_err() {
+            log::warn!("Rebalance context is already attached to a consumer");
+        }
+    }
+
+    /// Lets revocations flush the sinks `processor` writes to.
+    pub fn attach_processor(&self, processor: &Arc<Processor>) {
+        if self.processor.set(processor.clone()).is_err() {
+            log::warn!("Rebalance context already has a processor");
+        }
+    }
+
+    fn consumer(&self) -> Option<Arc<KafkaConsumer>> {
+        self.consumer.get().and_then(Weak::upgrade)
+    }
+
+    /// Stores the offsets of everything processed and commits them
+    /// synchronously. Returns false if the commit failed.
+    pub fn commit_processed(&self) -> bool {
+        let Some(consumer) = self.consumer() else {
+            return true;
+        };
+        self.in_flight.store(&consumer);
+        match consumer.commit_consumer_state(CommitMode::Sync) {
+            Ok(()) => log::info!("Committed processed offsets"),
+            Err(e) if e.rdkafka_error_code() == Some(RDKafkaErrorCode::NoOffset) => {
+                log::debug!("No processed offsets to commit");
+            }
+            Err(e) => {
+                log::error!("Failed to commit offsets: {}", e);
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Drops the queued messages of `revoked`, waits for the ones being
+    /// processed and flushes the sinks. Blocks the polling task, which is where
+    /// librdkafka runs this callback.
+    fn settle(&self, revoked: &[PartitionKey]) {
+        let runtime = match Handle::try_current() {
+            Ok(runtime) if runtime.runtime_flavor() == RuntimeFlavor::MultiThread => runtime,
+            _ => return,
+        };
+        tokio::task::block_in_place(|| {
+            runtime.block_on(async {
+                if !self.in_flight.drain(revoked, REVOKE_DRAIN_TIMEOUT).await {
+                    log::warn!(
+                        "Messages of revoked partitions are still being processed; \
+                         the next owner will redeliver them"
+                    );
+                }
+                if let Some(processor) = self.processor.get() {
+                    processor.flush().await;
+                }
+            })
+        });
+    }
+
+    fn notify(&self, event: RebalanceEvent) {
+        // The receiver only goes away during shutdown.
+        let _ = self.events.send(event);
+    }
+}
+
+/// Formats a partition list as e.g. `hello-topic[0, 2]`.
+fn describe(partitions: &TopicPartitionList) -> String {
+    let mut topics: Vec<(String, Vec<i32>)> = Vec::new();
+    for element in partitions.elements() {
+        match topics
+            .iter_mut()
+            .find(|(topic, _)| topic == element.topic())
+        {
+            Some((_, ids)) => ids.push(element.partition()),
+            None => topics.push((element.topic().to_string(), vec![element.partition()])),
+        }
+    }
+    topics
+        .iter()
+        .map(|(topic, ids)| format!("{}{:?}", topic, ids))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+impl ClientContext for RebalanceContext {
+    /// Called every `statistics.interval.ms`. Only partitions being fetched
+    /// are reported, so revoked partitions drop out of the gauge.
+    fn stats(&self, statistics: Statistics) {
+        self.partition_lag.reset();
+        for (topic, stats) in &statistics.topics {
+            for (partition, stats) in &stats.partitions {
+                // Partition -1 holds the topic's unassigned messages.
+                if *partition < 0 || stats.fetch_state == "none" {
+                    continue;
+                }
+                // Offsets are stored once processed; before the first one
+                // the committed offset is the best estimate.
+                let lag = if stats.consumer_lag_stored >= 0 {
+                    stats.consumer_lag_stored
+                } else {
+                    stats.consumer_lag
+                };
+                if lag >= 0 {
+                    self.partition_lag
+                        .with_label_values(&[topic, &partition.to_string()])
+                        .set(lag);
+                }
+            }
+        }
+    }
+}
+
+impl ConsumerContext for RebalanceContext {
+    fn pre_rebalance(&self, rebalance: &Rebalance) {
+        match rebalance {
+            Rebalance::Assign(partitions) => {
+                log::info!("Assigning partitions: {}", describe(partitions));
+                self.rebalances.with_label_values(&["assign"]).inc();
+            }
+            Rebalance::Revoke(partitions) => {
+                log::info!("Revoking partitions: {}", describe(partitions));
+                self.rebalances.with_label_values(&["revoke"]).inc();
+                let revoked: Vec<PartitionKey> = partitions
+                    .elements()
+                    .iter()
+                    .map(|element| (element.topic().to_string(), element.partition()))
+                    .collect();
+                self.settle(&revoked);
+                if !self.commit_processed() {
+                    self.revoke_commit_failures.inc();
+                }
+                self.in_flight.forget(&revoked);
+            }
+            Rebalance::Error(e) => {
+                log::error!("Rebalance failed: {}", e);
+                self.rebalances.with_label_values(&["error"]).inc();
+            }
+        }
+    }
+
+    fn post_rebalance(&self, rebalance: &Rebalance) {
+        if let Some(consumer) = self.consumer() {
+            match consumer.assignment() {
+                Ok(assignment) => self.assigned_partitions.set(assignment.count() as i64),
+                Err(e) => log::warn!("Failed to read partition assignment: {}", e),
+            }
+        }
+        match rebalance {
+            Rebalance::Assign(_) => self.notify(RebalanceEvent::Assigned),
+            Rebalance::Revoke(_) => self.notify(RebalanceEvent::Revoked),
+            Rebalance::Error(_) => {}
+        }
+    }
+
+    fn commit_callback(&self, result: KafkaResult<()>, offsets: &TopicPartitionList) {
+        match result {
+            Ok(()) => log::debug!("Committed offsets for {}", describe(offsets)),
+            Err(e) => log::warn!("Offset commit failed for {}: {}", describe(offsets), e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rdkafka::Offset;
+
+    fn partitions() -> TopicPartitionList {
+        let mut list = TopicPartitionList::new();
+        list.add_partition("hello-topic", 0);
+        list.add_partition("hello-topic", 2);
+        list.add_partition("other-topic", 1);
+        list
+    }
+
+    #[test]
+    fn describes_partition_lists() {
+        assert_eq!(describe(&partitions()), "hello-topic[0, 2], other-topic[1]");
+        let mut offsets = TopicPartitionList::new();
+        offsets
+            .add_partition_offset("hello-topic", 3, Offset::Offset(7))
+            .unwrap();
+        assert_eq!(describe(&offsets), "hello-topic[3]");
+    }
+
+    #[test]
+    fn parses_assignment_strategy() {
+        let strategy: AssignmentStrategy =
+            serde_json::from_value(serde_json::json!("cooperative-sticky")).unwrap();
+        assert_eq!(strategy, AssignmentStrategy::CooperativeSticky);
+        assert_eq!(strategy.as_str(), "cooperative-sticky");
+        assert!(serde_json::from_value::<AssignmentStrategy>(serde_json::json!("sticky")).is_err());
+    }
+
+    fn context() -> (RebalanceContext, mpsc::UnboundedReceiver<RebalanceEvent>) {
+        let registry = Registry::new();
+        let in_flight = InFlight::new(10, &registry).unwrap();
+        RebalanceContext::new(&registry, in_flight).unwrap()
+    }
+
+    #[test]
+    fn meters_rebalances_and_notifies_main_loop() {
+        let (context, mut events) = context();
+        let partitions = partitions();
+
+        context.pre_rebalance(&Rebalance::Assign(&partitions));
+        context.post_rebalance(&Rebalance::Assign(&partitions));
+        context.pre_rebalance(&Rebalance::Revoke(&partitions));
+        context.post_rebalance(&Rebalance::Revoke(&partitions));
+        context.pre_rebalance(&Rebalance::Error("boom".to_string()));
+
+        for (event, count) in [("assign", 1), ("revoke", 1), ("error", 1)] {
+            assert_eq!(context.rebalances.with_label_values(&[event]).get(), count);
+        }
+        assert_eq!(events.try_recv().unwrap(), RebalanceEvent::Assigned);
+        assert_eq!(events.try_recv().unwrap(), RebalanceEvent::Revoked);
+        assert!(events.try_recv().is_err());
+    }
+
+    #[test]
+    fn reports_partition_lag_from_statistics() {
+        use prometheus::core::Collector;
+        use rdkafka::statistics::{Partition, Topic};
+
+        let (context, _events) = context();
+        let partition = |id, fetch_state: &str, stored, committed| Partition {
+            partition: id,
+            fetch_state: fetch_state.to_string(),
+            consumer_lag_stored: stored,
+            consumer_lag: committed,
+            ..Default::default()
+        };
+        let mut topic = Topic::default();
+        for p in [
+            partition(-1, "none", -1, -1),
+            partition(0, "active", 12, 40),
+            partition(1, "active", -1, 7),
+            partition(2, "none", 5, 5),
+        ] {
+            topic.partitions.insert(p.partition, p);
+        }
+        let mut statistics = Statistics::default();
+        statistics.topics.insert("hello-topic".to_string(), topic);
+
+        context.stats(statistics);
+        let lag = |partition: &str| {
+            context
+                .partition_lag
+                .with_label_values(&["hello-topic", partition])
+                .get()
+        };
+        assert_eq!(lag("0"), 12);
+        assert_eq!(lag("1"), 7);
+
+        // Partitions no longer fetched drop out on the next report.
+        context.stats(Statistics::default());
+        assert!(context.partition_lag.collect()[0].get_metric().is_empty());
+    }
+}
@@ -0,0 +1,428 @@
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use rdkafka::message::{Headers, Message};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::fmt;
+use uuid::Uuid;
+
+pub const CONTENT_TYPE_JSON: &str = "application/json";
+const CONTENT_TYPE_TEXT: &str = "text/plain";
+const CONTENT_TYPE_BINARY: &str = "application/octet-stream";
+
+/// The JSON event published by the API's `SayHello`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct HelloMessage {
+    #[serde(default)]
+    pub event_id: Option<Uuid>,
+    pub name: String,
+    pub produced_at: DateTime<Utc>,
+}
+
+/// A message body: parsed JSON (stored as JSONB) or the exact bytes received.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Payload {
+    Json {
+        value: Value,
+        /// The text `value` was parsed from, until the pipeline changes it.
+        /// `Value` can't hold every number exactly, so this is what is stored.
+        text: Option<String>,
+    },
+    Bytes(Vec<u8>),
+}
+
+impl Payload {
+    /// Parses JSON payloads and keeps everything else as raw bytes, returning
+    /// the payload with its content type. A `content-type` header wins over
+    /// sniffing, except that a header claiming JSON for unparseable bytes is
+    /// ignored.
+    pub fn classify(bytes: &[u8], content_type_header: Option<&str>) -> (Payload, String) {
+        let declared = content_type_header.map(str::to_string);
+        let declared_json = declared
+            .as_deref()
+            .is_some_and(|ct| ct.starts_with(CONTENT_TYPE_JSON));
+
+        if declared.is_none() || declared_json {
+            if let Ok(value) = serde_json::from_slice::<Value>(bytes) {
+                let text = String::from_utf8(bytes.to_vec()).ok();
+                return (Payload::Json { value, text }, CONTENT_TYPE_JSON.to_string());
+            }
+        }
+
+        let content_type = match declared {
+            Some(ct) if !declared_json => ct,
+            _ if std::str::from_utf8(bytes).is_ok() => CONTENT_TYPE_TEXT.to_string(),
+            _ => CONTENT_TYPE_BINARY.to_string(),
+        };
+        (Payload::Bytes(bytes.to_vec()), content_type)
+    }
+
+    /// A JSON payload built rather than received, so without original text.
+    #[cfg(test)]
+    pub fn json(value: Value) -> Self {
+        Payload::Json { value, text: None }
+    }
+
+    pub fn as_json(&self) -> Option<&Value> {
+        match self {
+            Payload::Json { value, .. } => Some(value),
+            Payload::Bytes(_) => None,
+        }
+    }
+
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Payload::Json { .. } => None,
+            Payload::Bytes(bytes) => Some(bytes),
+        }
+    }
+
+    /// JSON payloads as text: as received if unchanged, else re-serialized.
+    pub fn json_text(&self) -> Option<String> {
+        match self {
+            Payload::Json {
+                text: Some(text), ..
+            } => Some(text.clone()),
+            Payload::Json { value, .. } => Some(value.to_string()),
+            Payload::Bytes(_) => None,
+        }
+    }
+
+    /// The `(payload_json, payload_bytes)` columns of a stored record. JSON is
+    /// stored from its text so Postgres keeps every digit. JSON containing a
+    /// NUL character, which JSONB rejects, is stored as bytes instead.
+    pub fn columns(&self) -> (Option<String>, Option<Vec<u8>>) {
+        match self {
+            Payload::Json { value, .. } if contains_nul(value) => (None, Some(self.to_bytes())),
+            Payload::Json { .. } => (self.json_text(), None),
+            Payload::Bytes(bytes) => (None, Some(bytes.clone())),
+        }
+    }
+
+    /// The payload as it would appear on the wire; changed JSON is re-serialized.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            Payload::Json { .. } => self.json_text().unwrap_or_default().into_bytes(),
+            Payload::Bytes(bytes) => bytes.clone(),
+        }
+    }
+}
+
+fn contains_nul(value: &Value) -> bool {
+    match value {
+        Value::String(s) => s.contains('\0'),
+        Value::Array(items) => items.iter().any(contains_nul),
+        Value::Object(object) => object
+            .iter()
+            .any(|(key, value)| key.contains('\0') || contains_nul(value)),
+        _ => false,
+    }
+}
+
+impl fmt::Display for Payload {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Payload::Json {
+                text: Some(text), ..
+            } => write!(f, "{}", text),
+            Payload::Json { value, .. } => write!(f, "{}", value),
+            Payload::Bytes(bytes) => write!(f, "{}", String::from_utf8_lossy(bytes)),
+        }
+    }
+}
+
+/// A consumed Kafka record with everything that gets persisted about it.
+#[derive(Debug, Clone)]
+pub struct MessageRecord {
+    pub topic: String,
+    pub partition: i32,
+    pub offset: i64,
+    pub key: Option<Vec<u8>>,
+    /// Headers in order as `[{"key": ..., "value": ...}]`. Values are UTF-8
+    /// strings, base64 with `"encoding": "base64"` when not valid UTF-8, and
+    /// `null` when absent.
+    pub headers: Value,
+    pub kafka_timestamp: Option<DateTime<Utc>>,
+    pub payload: Payload,
+    pub content_type: String,
+    pub hello: Option<HelloMessage>,
+    /// When the record was received from Kafka, before any insert retries.
+    pub consumed_at: DateTime<Utc>,
+}
+
+impl MessageRecord {
+    /// Returns `None` for records without a payload (e.g. tombstones).
+    pub fn from_kafka<M: Message>(message: &M) -> Option<Self> {
+        let bytes = message.payload()?;
+        let content_type_header = message.headers().and_then(|headers| {
+            headers
+                .iter()
+                .find(|h| h.key.eq_ignore_ascii_case("content-type"))
+                .and_then(|h| h.value)
+                .and_then(|v| std::str::from_utf8(v).ok())
+        });
+        let (payload, content_type) = Payload::classify(bytes, content_type_header);
+        let hello = payload
+            .as_json()
+            .and_then(|value| serde_json::from_value::<HelloMessage>(value.clone()).ok());
+
+        Some(Self {
+            topic: message.topic().to_string(),
+            partition: message.partition(),
+            offset: message.offset(),
+            key: message.key().map(<[u8]>::to_vec),
+            headers: message.headers().map_or_else(|| json!([]), headers_json),
+            kafka_timestamp: message
+                .timestamp()
+                .to_millis()
+                .and_then(DateTime::<Utc>::from_timestamp_millis),
+            payload,
+            content_type,
+            hello,
+            consumed_at: Utc::now(),
+        })
+    }
+
+    /// A JSON record on `default-topic` partition 0 for tests.
+    #[cfg(test)]
+    pub fn sample(offset: i64) -> Self {
+        Self {
+            topic: "default-topic".to_string(),
+            partition: 0,
+            offset,
+            key: Some(b"Bob".to_vec()),
+            headers: json!([]),
+            kafka_timestamp: None,
+            payload: Payload::json(json!({"name": "Bob"})),
+            content_type: CONTENT_TYPE_JSON.to_string(),
+            hello: None,
+            consumed_at: Utc::now(),
+        }
+    }
+
+    pub fn event_id(&self) -> Option<Uuid> {
+        self.hello.as_ref().and_then(|h| h.event_id)
+    }
+
+    pub fn produced_at(&self) -> Option<DateTime<Utc>> {
+        self.hello.as_ref().map(|h| h.produced_at)
+    }
+
+    /// A self-describing JSON document for line-oriented sinks. JSON payloads
+    /// are embedded as-is, text as a string and other bytes as base64 with
+    /// `"payload_encoding": "base64"`.
+    pub fn to_json(&self) -> Value {
+        let (payload, encoding) = match &self.payload {
+            Payload::Json { value, .. } => (value.clone(), "json"),
+            Payload::Bytes(bytes) => match std::str::from_utf8(bytes) {
+                Ok(text) => (Value::String(text.to_string()), "text"),
+                Err(_) => (
+                    Value::String(base64::engine::general_purpose::STANDARD.encode(bytes)),
+                    "base64",
+                ),
+            },
+        };
+        json!({
+            "topic": self.topic,
+            "partition": self.partition,
+            "offset": self.offset,
+            "key": self.key.as_ref().map(|k| String::from_utf8_lossy(k).into_owned()),
+            "headers": self.headers,
+            "kafka_timestamp": self.kafka_timestamp,
+            "produced_at": self.produced_at(),
+            "consumed_at": self.consumed_at,
+            "event_id": self.event_id(),
+            "content_type": self.content_type,
+            "payload_encoding": encoding,
+            "payload": payload,
+        })
+    }
+}
+
+/// Header values that are valid UTF-8 are stored as strings; anything else,
+/// such as binary trace contexts, as base64 with `"encoding": "base64"`.
+fn headers_json<H: Headers>(headers: &H) -> Value {
+    Value::Array(
+        headers
+            .iter()
+            .map(
+                |header| match header.value.map(|v| (v, std::str::from_utf8(v))) {
+                    Some((_, Ok(text))) => json!({"key": header.key, "value": text}),
+                    Some((bytes, Err(_))) => json!({
+                        "key": header.key,
+                        "value": base64::engine::general_purpose::STANDARD.encode(bytes),
+                        "encoding": "base64",
+                    }),
+                    None => json!({"key": header.key, "value": null}),
+                },
+            )
+            .collect(),
+    )
+}
+
+/// The raw bytes of a header stored by [`headers_json`]. Returns `None` for a
+/// header without a value or one that can't be decoded.
+pub fn header_value(header: &Value) -> Option<Vec<u8>> {
+    let value = header["value"].as_str()?;
+    match header["encoding"].as_str() {
+        Some("base64") => base64::engine::general_purpose::STANDARD.decode(value).ok(),
+        _ => Some(value.as_bytes().to_vec()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rdkafka::message::{Header, OwnedHeaders, OwnedMessage, Timestamp};
+
+    fn message(payload: &str, headers: Option<OwnedHeaders>) -> OwnedMessage {
+        OwnedMessage::new(
+            Some(payload.as_bytes().to_vec()),
+            Some(b"Bob".to_vec()),
+            "default-topic".to_string(),
+            Timestamp::CreateTime(1_700_000_000_123),
+            2,
+            42,
+            headers,
+        )
+    }
+
+    #[test]
+    fn captures_kafka_metadata() {
+        let headers = OwnedHeaders::new()
+            .insert(Header {
+                key: "trace-id",
+                value: Some("abc"),
+            })
+            .insert::<str>(Header {
+                key: "empty",
+                value: None,
+            });
+        let record = MessageRecord::from_kafka(&message("plain text", Some(headers))).unwrap();
+
+        assert_eq!(record.topic, "default-topic");
+        assert_eq!(record.partition, 2);
+        assert_eq!(record.offset, 42);
+        assert_eq!(record.key.as_deref(), Some(&b"Bob"[..]));
+        assert_eq!(
+            record.headers,
+            json!([{"key": "trace-id", "value": "abc"}, {"key": "empty", "value": null}])
+        );
+        assert_eq!(
+            record.kafka_timestamp.unwrap().to_rfc3339(),
+            "2023-11-14T22:13:20.123+00:00"
+        );
+        assert!(record.hello.is_none());
+        assert_eq!(record.produced_at(), None);
+        assert_eq!(record.payload, Payload::Bytes(b"plain text".to_vec()));
+        assert_eq!(record.content_type, "text/plain");
+    }
+
+    #[test]
+    fn keeps_binary_headers_intact() {
+        let binary = [0x00, 0xff, 0xfe, 0x41];
+        let headers = OwnedHeaders::new()
+            .insert(Header {
+                key: "traceparent",
+                value: Some(&binary[..]),
+            })
+            .insert(Header {
+                key: "trace-id",
+                value: Some("abc"),
+            });
+        let record = MessageRecord::from_kafka(&message("x", Some(headers))).unwrap();
+
+        assert_eq!(
+            record.headers,
+            json!([
+                {"key": "traceparent", "value": "AP/+QQ==", "encoding": "base64"},
+                {"key": "trace-id", "value": "abc"},
+            ])
+        );
+        let values: Vec<_> = record
+            .headers
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(header_value)
+            .collect();
+        assert_eq!(values, [Some(binary.to_vec()), Some(b"abc".to_vec())]);
+        assert_eq!(header_value(&json!({"key": "empty", "value": null})), None);
+    }
+
+    #[test]
+    fn classifies_payloads() {
+        let (payload, content_type) = Payload::classify(br#"{"a": 1}"#, None);
+        assert_eq!(payload.as_json(), Some(&json!({"a": 1})));
+        assert_eq!(content_type, "application/json");
+
+        let (payload, content_type) = Payload::classify(&[0xff, 0x00, 0xfe], None);
+        assert_eq!(payload, Payload::Bytes(vec![0xff, 0x00, 0xfe]));
+        assert_eq!(content_type, "application/octet-stream");
+
+        // An explicit non-JSON content type keeps JSON-looking bytes raw.
+        let (payload, content_type) = Payload::classify(b"[1]", Some("text/csv"));
+        assert_eq!(payload, Payload::Bytes(b"[1]".to_vec()));
+        assert_eq!(content_type, "text/csv");
+
+        // A JSON content type on invalid JSON falls back to sniffing.
+        let (payload, content_type) =
+            Payload::classify(b"not json", Some("application/json; charset=utf-8"));
+        assert_eq!(payload, Payload::Bytes(b"not json".to_vec()));
+        assert_eq!(content_type, "text/plain");
+    }
+
+    #[test]
+    fn stores_json_exactly_as_received() {
+        let received = r#"{"id": 123456789012345678901234567890, "ratio": 0.10000000000000000555}"#;
+        let (payload, _) = Payload::classify(received.as_bytes(), None);
+        assert_eq!(payload.columns(), (Some(received.to_string()), None));
+        assert_eq!(payload.to_bytes(), received.as_bytes());
+
+        // JSONB can't hold NUL, so such payloads go to payload_bytes.
+        let with_nul = r#"{"name": "Bo\u0000b"}"#;
+        let (payload, content_type) = Payload::classify(with_nul.as_bytes(), None);
+        assert_eq!(content_type, "application/json");
+        assert!(payload.as_json().is_some());
+        assert_eq!(
+            payload.columns(),
+            (None, Some(with_nul.as_bytes().to_vec()))
+        );
+    }
+
+    #[test]
+    fn parses_hello_event_fields() {
+        let id = Uuid::new_v4();
+        let payload = format!(
+            r#"{{"event_id": "{}", "name": "Bob", "produced_at": "2024-03-09T12:00:00Z"}}"#,
+            id
+        );
+        let record = MessageRecord::from_kafka(&message(&payload, None)).unwrap();
+
+        assert_eq!(record.event_id(), Some(id));
+        assert_eq!(
+            record.produced_at().unwrap().to_rfc3339(),
+            "2024-03-09T12:00:00+00:00"
+        );
+        assert_eq!(record.headers, json!([]));
+        assert_eq!(record.content_type, "application/json");
+        assert!(record.payload.as_json().is_some());
+    }
+
+    #[test]
+    fn renders_record_as_json_document() {
+        let record = MessageRecord::from_kafka(&message(r#"{"name": "Bob"}"#, None)).unwrap();
+        let doc = record.to_json();
+        assert_eq!(doc["topic"], "default-topic");
+        assert_eq!(doc["offset"], 42);
+        assert_eq!(doc["key"], "Bob");
+        assert_eq!(doc["payload_encoding"], "json");
+        assert_eq!(doc["payload"], json!({"name": "Bob"}));
+
+        let mut binary = record.clone();
+        binary.payload = Payload::Bytes(vec![0xff, 0x00]);
+        let doc = binary.to_json();
+        assert_eq!(doc["payload_encoding"], "base64");
+        assert_eq!(doc["payload"], "/wA=");
+    }
+}
@@ -0,0 +1,389 @@
+use crate::breaker::CircuitBreaker;
+use crate::config::ConsumerConfig;
+use crate::db::Pool;
+use crate::inflight::InFlight;
+use crate::record::MessageRecord;
+use async_trait::async_trait;
+use prometheus::{IntCounter, IntCounterVec, Registry};
+use serde::Deserialize;
+use std::sync::Arc;
+use std::time::Duration;
+use terrarium_core::metrics;
+use terrarium_core::reload::Live;
+use tokio::time::sleep;
+
+mod file;
+mod kafka;
+mod parquet;
+mod postgres;
+mod stdout;
+
+pub use file::{FileSink, FileSinkSettings};
+pub use kafka::{KafkaSink, KafkaSinkSettings};
+pub use parquet::{ParquetSink, ParquetSinkSettings};
+pub use postgres::PostgresSink;
+pub use stdout::StdoutSink;
+
+pub type SinkError = Box<dyn std::error::Error + Send + Sync>;
+
+/// A destination for consumed records. `Sinks::write` calls `write` on each
+/// configured sink in order, retrying failures per `sink_retry`.
+#[async_trait]
+pub trait Sink: Send + Sync {
+    /// Label used in logs and the `sink` metric label.
+    fn name(&self) -> &str;
+
+    /// Whether failures also count toward `consumer_db_insert_failures_total`,
+    /// which predates the other sinks.
+    fn counts_as_db_insert(&self) -> bool {
+        false
+    }
+
+    async fn write(&self, record: &MessageRecord) -> Result<(), SinkError>;
+
+    /// Persists anything buffered; called on shutdown.
+    async fn flush(&self) -> Result<(), SinkError> {
+        Ok(())
+    }
+}
+
+/// One entry of the consumer's `sinks` list, selected by `type`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum SinkSettings {
+    Postgres,
+    File(FileSinkSettings),
+    Stdout,
+    Kafka(KafkaSinkSettings),
+    Parquet(ParquetSinkSettings),
+}
+
+pub fn default_sinks() -> Vec<SinkSettings> {
+    vec![SinkSettings::Postgres]
+}
+
+/// Checks the `sinks` list against the rest of the consumer config.
+pub fn validate(
+    sinks: &[SinkSettings],
+    source_topic: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if sinks.is_empty() {
+        return Err("sinks must contain at least one sink".into());
+    }
+    if sinks
+        .iter()
+        .filter(|s| matches!(s, SinkSettings::Postgres))
+        .count()
+        > 1
+    {
+        return Err("sinks: postgres may only be listed once".into());
+    }
+    for sink in sinks {
+        match sink {
+            SinkSettings::File(settings) => settings.validate()?,
+            SinkSettings::Kafka(settings) => settings.validate(source_topic)?,
+            SinkSettings::Parquet(settings) => settings.validate()?,
+            SinkSettings::Postgres | SinkSettings::Stdout => {}
+        }
+    }
+    Ok(())
+}
+
+/// How often a failed sink write is attempted. Applied on config reload.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetrySettings {
+    /// Attempts per record and sink, including the first.
+    pub max_attempts: u32,
+    pub backoff_ms: u64,
+}
+
+impl Default for RetrySettings {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            backoff_ms: 1000,
+        }
+    }
+}
+
+impl RetrySettings {
+    pub fn validate(&self) -> Result<(), Box<dyn std::error::Error>> {
+        if self.max_attempts == 0 {
+            return Err("sink_retry.max_attempts must be greater than 0".into());
+        }
+        Ok(())
+    }
+}
+
+/// Fans records out to every configured sink, retrying each one separately.
+pub struct Sinks {
+    sinks: Vec<Box<dyn Sink>>,
+    retry: Arc<Live<RetrySettings>>,
+    writes: IntCounterVec,
+    failures: IntCounterVec,
+    /// Kept from before there were several sinks; see `Sink::counts_as_db_insert`.
+    db_insert_failures: IntCounter,
+}
+
+impl Sinks {
+    /// Builds the sinks listed in `config`. `pool` must be set when a postgres
+    /// sink is listed; `breaker` guards its inserts.
+    pub fn new(
+        config: &ConsumerConfig,
+        pool: Option<&Pool>,
+        breaker: &Arc<CircuitBreaker>,
+        retry: Arc<Live<RetrySettings>>,
+        in_flight: &Arc<InFlight>,
+        registry: &Registry,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut sinks: Vec<Box<dyn Sink>> = Vec::new();
+        for entry in &config.sinks {
+            let sink: Box<dyn Sink> = match entry {
+                SinkSettings::Postgres => {
+                    let pool = pool.ok_or("the postgres sink requires a database section")?;
+                    Box::new(PostgresSink::new(pool.clone(), breaker.clone()))
+                }
+                SinkSettings::File(settings) => Box::new(FileSink::new(settings.clone())?),
+                SinkSettings::Stdout => Box::new(StdoutSink),
+                SinkSettings::Kafka(settings) => Box::new(KafkaSink::new(
+                    settings.clone(),
+                    &config.kafka_broker,
+                    &config.kafka_security,
+                )?),
+                SinkSettings::Parquet(settings) => {
+                    Box::new(ParquetSink::new(settings.clone(), in_flight.clone())?)
+                }
+            };
+            log::info!("Writing messages to {} sink", sink.name());
+            sinks.push(sink);
+        }
+        Self::from_sinks(sinks, retry, registry).map_err(Into::into)
+    }
+
+    pub(crate) fn from_sinks(
+        sinks: Vec<Box<dyn Sink>>,
+        retry: Arc<Live<RetrySettings>>,
+        registry: &Registry,
+    ) -> Result<Self, prometheus::Error> {
+        Ok(Self {
+            sinks,
+            retry,
+            writes: metrics::register_int_counter_vec(
+                registry,
+                "consumer_sink_writes_total",
+                "Total number of messages written, by sink",
+                &["sink"],
+            )?,
+            failures: metrics::register_int_counter_vec(
+                registry,
+                "consumer_sink_failures_total",
+                "Total number of messages a sink failed to write after retries, by sink",
+                &["sink"],
+            )?,
+            db_insert_failures: metrics::register_int_counter(
+                registry,
+                "consumer_db_insert_failures_total",
+                "Total number of messages that could not be inserted into Postgres",
+            )?,
+        })
+    }
+
+    /// Writes `record` to every sink, retrying each per `sink_retry`. Returns
+    /// false if any sink still failed; the other sinks are written regardless.
+    pub async fn write(&self, record: &MessageRecord) -> bool {
+        let retry = self.retry.get();
+        let mut all_written = true;
+        for sink in &self.sinks {
+            if write_with_retries(sink.as_ref(), record, &retry).await {
+                self.writes.with_label_values(&[sink.name()]).inc();
+            } else {
+                log::error!(
+                    "Failed to write message to {} sink after {} attempts",
+                    sink.name(),
+                    retry.max_attempts
+                );
+                self.failures.with_label_values(&[sink.name()]).inc();
+                if sink.counts_as_db_insert() {
+                    self.db_insert_failures.inc();
+                }
+                all_written = false;
+            }
+        }
+        all_written
+    }
+
+    /// The retry policy currently in effect.
+    pub fn retry(&self) -> Arc<RetrySettings> {
+        self.retry.get()
+    }
+
+    pub async fn flush(&self) {
+        for sink in &self.sinks {
+            if let Err(e) = sink.flush().await {
+                log::error!("Failed to flush {} sink: {}", sink.name(), e);
+            }
+        }
+    }
+}
+
+async fn write_with_retries(
+    sink: &dyn Sink,
+    record: &MessageRecord,
+    retry: &RetrySettings,
+) -> bool {
+    for attempt in 1..=retry.max_attempts {
+        match sink.write(record).await {
+            Ok(()) => {
+                log::debug!(
+                    "Wrote message to {} sink - Topic: {}, Partition: {}, Offset: {}",
+                    sink.name(),
+                    record.topic,
+                    record.partition,
+                    record.offset
+                );
+                return true;
+            }
+            Err(e) => {
+                log::warn!(
+                    "Failed to write message to {} sink (attempt {}/{}): {}",
+                    sink.name(),
+                    attempt,
+                    retry.max_attempts,
+                    e
+                );
+                if attempt < retry.max_attempts {
+                    sleep(Duration::from_millis(retry.backoff_ms)).await;
+                }
+            }
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    struct Flaky {
+        name: &'static str,
+        db_insert: bool,
+        failures_left: AtomicUsize,
+        calls: Arc<AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl Sink for Flaky {
+        fn name(&self) -> &str {
+            self.name
+        }
+
+        fn counts_as_db_insert(&self) -> bool {
+            self.db_insert
+        }
+
+        async fn write(&self, _record: &MessageRecord) -> Result<(), SinkError> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            let left = self.failures_left.load(Ordering::SeqCst);
+            if left > 0 {
+                self.failures_left.store(left - 1, Ordering::SeqCst);
+                return Err("unavailable".into());
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn parses_sink_list() {
+        let sinks: Vec<SinkSettings> = serde_json::from_str(
+            r#"[
+                {"type": "postgres"},
+                {"type": "stdout"},
+                {"type": "file", "dir": "/tmp/messages"},
+                {"type": "kafka", "topic": "hello-archive"},
+                {"type": "parquet", "dir": "/tmp/archive", "row_group_size": 500}
+            ]"#,
+        )
+        .unwrap();
+        assert!(matches!(sinks[0], SinkSettings::Postgres));
+        assert!(matches!(sinks[1], SinkSettings::Stdout));
+        assert!(matches!(&sinks[2], SinkSettings::File(f) if f.max_bytes == 100 * 1024 * 1024));
+        assert!(matches!(&sinks[3], SinkSettings::Kafka(k) if k.topic == "hello-archive"));
+        assert!(matches!(&sinks[4], SinkSettings::Parquet(p) if p.row_group_size == 500));
+        validate(&sinks, "default-topic").unwrap();
+    }
+
+    #[test]
+    fn rejects_invalid_sink_lists() {
+        assert!(validate(&[], "default-topic").is_err());
+        assert!(validate(&[SinkSettings::Postgres, SinkSettings::Postgres], "t").is_err());
+        let loopback: Vec<SinkSettings> =
+            serde_json::from_str(r#"[{"type": "kafka", "topic": "default-topic"}]"#).unwrap();
+        assert!(validate(&loopback, "default-topic").is_err());
+        assert!(serde_json::from_str::<Vec<SinkSettings>>(r#"[{"type": "s3"}]"#).is_err());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn retries_each_sink_independently() {
+        let flaky_calls = Arc::new(AtomicUsize::new(0));
+        let broken_calls = Arc::new(AtomicUsize::new(0));
+        let registry = Registry::new();
+        let retry = Live::new(RetrySettings::default());
+        let sinks = Sinks::from_sinks(
+            vec![
+                Box::new(Flaky {
+                    name: "flaky",
+                    db_insert: false,
+                    failures_left: AtomicUsize::new(2),
+                    calls: flaky_calls.clone(),
+                }),
+                Box::new(Flaky {
+                    name: "broken",
+                    db_insert: false,
+                    failures_left: AtomicUsize::new(usize::MAX),
+                    calls: broken_calls.clone(),
+                }),
+            ],
+            retry.clone(),
+            &registry,
+        )
+        .unwrap();
+
+        assert!(!sinks.write(&MessageRecord::sample(1)).await);
+        assert_eq!(flaky_calls.load(Ordering::SeqCst), 3);
+        assert_eq!(broken_calls.load(Ordering::SeqCst), 3);
+        assert_eq!(sinks.writes.with_label_values(&["flaky"]).get(), 1);
+        assert_eq!(sinks.failures.with_label_values(&["broken"]).get(), 1);
+        // Only sinks that opt in count toward DB insert failures.
+        assert_eq!(sinks.db_insert_failures.get(), 0);
+
+        // A reloaded retry policy applies to the next record.
+        retry.set(RetrySettings {
+            max_attempts: 1,
+            backoff_ms: 0,
+        });
+        assert!(!sinks.write(&MessageRecord::sample(2)).await);
+        assert_eq!(broken_calls.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test]
+    async fn counts_db_insert_failures_for_sinks_that_opt_in() {
+        let sinks = Sinks::from_sinks(
+            vec![Box::new(Flaky {
+                name: "primary",
+                db_insert: true,
+                failures_left: AtomicUsize::new(usize::MAX),
+                calls: Arc::new(AtomicUsize::new(0)),
+            })],
+            Live::new(RetrySettings {
+                max_attempts: 1,
+                backoff_ms: 0,
+            }),
+            &Registry::new(),
+        )
+        .unwrap();
+        assert!(!sinks.write(&MessageRecord::sample(1)).await);
+        assert_eq!(sinks.db_insert_failures.get(), 1);
+    }
+}
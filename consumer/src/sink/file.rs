@@ -0,0 +1,210 @@
+use super::{Sink, SinkError};
+use crate::record::MessageRecord;
+use async_trait::async_trait;
+use chrono::Utc;
+use serde::Deserialize;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use tokio::fs::{self, File};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct FileSinkSettings {
+    /// Directory the files are written to; created if missing.
+    pub dir: PathBuf,
+    #[serde(default = "default_prefix")]
+    pub prefix: String,
+    /// Start a new file once the current one reaches this size.
+    #[serde(default = "default_max_bytes")]
+    pub max_bytes: u64,
+    /// Start a new file once the current one is this old, even if small.
+    #[serde(default = "default_max_age_secs")]
+    pub max_age_secs: u64,
+}
+
+fn default_prefix() -> String {
+    "messages".to_string()
+}
+
+fn default_max_bytes() -> u64 {
+    100 * 1024 * 1024
+}
+
+fn default_max_age_secs() -> u64 {
+    3600
+}
+
+impl FileSinkSettings {
+    pub fn validate(&self) -> Result<(), Box<dyn std::error::Error>> {
+        if self.max_bytes == 0 {
+            return Err("sinks: file max_bytes must be greater than 0".into());
+        }
+        if self.max_age_secs == 0 {
+            return Err("sinks: file max_age_secs must be greater than 0".into());
+        }
+        Ok(())
+    }
+}
+
+struct OpenFile {
+    file: File,
+    path: PathBuf,
+    bytes: u64,
+    opened_at: Instant,
+}
+
+/// Appends records as newline-delimited JSON (see [`MessageRecord::to_json`]),
+/// rotating to `<prefix>-<UTC timestamp>.ndjson` by size and age.
+pub struct FileSink {
+    settings: FileSinkSettings,
+    current: Mutex<Option<OpenFile>>,
+}
+
+impl FileSink {
+    pub fn new(settings: FileSinkSettings) -> Result<Self, Box<dyn std::error::Error>> {
+        std::fs::create_dir_all(&settings.dir)?;
+        Ok(Self {
+            settings,
+            current: Mutex::new(None),
+        })
+    }
+
+    fn needs_rotation(&self, open: &OpenFile, incoming: u64) -> bool {
+        let full = open.bytes > 0 && open.bytes + incoming > self.settings.max_bytes;
+        let expired = open.opened_at.elapsed() >= Duration::from_secs(self.settings.max_age_secs);
+        full || expired
+    }
+
+    async fn open_next(&self) -> std::io::Result<OpenFile> {
+        let stamp = Utc::now().format("%Y%m%dT%H%M%S%.3fZ");
+        let mut path = self
+            .settings
+            .dir
+            .join(format!("{}-{}.ndjson", self.settings.prefix, stamp));
+        // Several rotations within one millisecond get a numeric suffix.
+        let mut n = 1;
+        while fs::try_exists(&path).await? {
+            path = self
+                .settings
+                .dir
+                .join(format!("{}-{}-{}.ndjson", self.settings.prefix, stamp, n));
+            n += 1;
+        }
+        let file = File::create(&path).await?;
+        log::info!("Writing messages to {}", path.display());
+        Ok(OpenFile {
+            file,
+            path,
+            bytes: 0,
+            opened_at: Instant::now(),
+        })
+    }
+}
+
+#[async_trait]
+impl Sink for FileSink {
+    fn name(&self) -> &str {
+        "file"
+    }
+
+    async fn write(&self, record: &MessageRecord) -> Result<(), SinkError> {
+        let mut line = record.to_json().to_string();
+        line.push('\n');
+        let incoming = line.len() as u64;
+
+        let mut current = self.current.lock().await;
+        if let Some(open) = current.as_mut() {
+            if self.needs_rotation(open, incoming) {
+                open.file.flush().await?;
+                log::info!("Rotated {} after {} bytes", open.path.display(), open.bytes);
+                *current = None;
+            }
+        }
+        if current.is_none() {
+            *current = Some(self.open_next().await?);
+        }
+        let open = current.as_mut().expect("file opened above");
+        open.file.write_all(line.as_bytes()).await?;
+        open.file.flush().await?;
+        open.bytes += incoming;
+        Ok(())
+    }
+
+    async fn flush(&self) -> Result<(), SinkError> {
+        if let Some(open) = self.current.lock().await.as_mut() {
+            open.file.sync_all().await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ndjson_files(dir: &std::path::Path) -> Vec<PathBuf> {
+        let mut files: Vec<PathBuf> = std::fs::read_dir(dir)
+            .unwrap()
+            .map(|e| e.unwrap().path())
+            .collect();
+        files.sort();
+        files
+    }
+
+    #[tokio::test]
+    async fn writes_one_json_document_per_line() {
+        let dir = tempfile::tempdir().unwrap();
+        let sink = FileSink::new(FileSinkSettings {
+            dir: dir.path().to_path_buf(),
+            prefix: default_prefix(),
+            max_bytes: default_max_bytes(),
+            max_age_secs: default_max_age_secs(),
+        })
+        .unwrap();
+
+        sink.write(&MessageRecord::sample(1)).await.unwrap();
+        sink.write(&MessageRecord::sample(2)).await.unwrap();
+
+        let files = ndjson_files(dir.path());
+        assert_eq!(files.len(), 1);
+        let contents = std::fs::read_to_string(&files[0]).unwrap();
+        let offsets: Vec<i64> = contents
+            .lines()
+            .map(|l| {
+                serde_json::from_str::<serde_json::Value>(l).unwrap()["offset"]
+                    .as_i64()
+                    .unwrap()
+            })
+            .collect();
+        assert_eq!(offsets, vec![1, 2]);
+    }
+
+    #[tokio::test]
+    async fn rotates_when_file_is_full() {
+        let dir = tempfile::tempdir().unwrap();
+        let sink = FileSink::new(FileSinkSettings {
+            dir: dir.path().to_path_buf(),
+            prefix: "archive".to_string(),
+            max_bytes: 1,
+            max_age_secs: default_max_age_secs(),
+        })
+        .unwrap();
+
+        for offset in 0..3 {
+            sink.write(&MessageRecord::sample(offset)).await.unwrap();
+        }
+
+        let files = ndjson_files(dir.path());
+        assert_eq!(files.len(), 3);
+        for file in files {
+            assert!(file
+                .file_name()
+                .unwrap()
+                .to_str()
+                .unwrap()
+                .starts_with("archive-"));
+            assert_eq!(std::fs::read_to_string(file).unwrap().lines().count(), 1);
+        }
+    }
+}
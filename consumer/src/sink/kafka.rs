@@ -0,0 +1,126 @@
+use super::{Sink, SinkError};
+use crate::record::{self, MessageRecord};
+use async_trait::async_trait;
+use rdkafka::config::ClientConfig;
+use rdkafka::message::{Header, OwnedHeaders};
+use rdkafka::producer::{FutureProducer, FutureRecord};
+use serde::Deserialize;
+use std::time::Duration;
+use terrarium_core::kafka_security::KafkaSecurity;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct KafkaSinkSettings {
+    pub topic: String,
+    /// Defaults to the consumer's `kafka_broker` and `kafka_security`.
+    pub brokers: Option<String>,
+    #[serde(default = "default_send_timeout_ms")]
+    pub send_timeout_ms: u64,
+}
+
+fn default_send_timeout_ms() -> u64 {
+    5000
+}
+
+impl KafkaSinkSettings {
+    pub fn validate(&self, source_topic: &str) -> Result<(), Box<dyn std::error::Error>> {
+        if self.topic.is_empty() {
+            return Err("sinks: kafka topic is required".into());
+        }
+        if self.brokers.is_none() && self.topic == source_topic {
+            return Err("sinks: kafka topic must differ from the consumed topic".into());
+        }
+        Ok(())
+    }
+}
+
+/// Republishes records, with their key and headers, to another topic.
+pub struct KafkaSink {
+    producer: FutureProducer,
+    topic: String,
+    send_timeout: Duration,
+}
+
+impl KafkaSink {
+    pub fn new(
+        settings: KafkaSinkSettings,
+        kafka_broker: &str,
+        kafka_security: &KafkaSecurity,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut client_config = ClientConfig::new();
+        match &settings.brokers {
+            Some(brokers) => {
+                client_config.set("bootstrap.servers", brokers);
+            }
+            None => {
+                client_config.set("bootstrap.servers", kafka_broker);
+                for (key, value) in kafka_security.properties()? {
+                    client_config.set(key, value);
+                }
+            }
+        }
+        Ok(Self {
+            producer: client_config.create()?,
+            topic: settings.topic,
+            send_timeout: Duration::from_millis(settings.send_timeout_ms),
+        })
+    }
+}
+
+fn owned_headers(record: &MessageRecord) -> OwnedHeaders {
+    let mut headers = OwnedHeaders::new();
+    for header in record.headers.as_array().into_iter().flatten() {
+        let Some(key) = header["key"].as_str() else {
+            continue;
+        };
+        headers = headers.insert(Header {
+            key,
+            value: record::header_value(header).as_deref(),
+        });
+    }
+    headers
+}
+
+#[async_trait]
+impl Sink for KafkaSink {
+    fn name(&self) -> &str {
+        "kafka"
+    }
+
+    async fn write(&self, record: &MessageRecord) -> Result<(), SinkError> {
+        let payload = record.payload.to_bytes();
+        let mut kafka_record = FutureRecord::to(&self.topic)
+            .payload(&payload)
+            .headers(owned_headers(record));
+        if let Some(key) = &record.key {
+            kafka_record = kafka_record.key(key);
+        }
+        self.producer
+            .send(kafka_record, self.send_timeout)
+            .await
+            .map_err(|(e, _)| Box::new(e) as SinkError)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rdkafka::message::Headers;
+    use serde_json::json;
+
+    #[test]
+    fn forwards_headers() {
+        let mut record = MessageRecord::sample(1);
+        record.headers = json!([
+            {"key": "trace-id", "value": "abc"},
+            {"key": "empty", "value": null},
+            {"key": "traceparent", "value": "AP/+QQ==", "encoding": "base64"},
+        ]);
+        let headers = owned_headers(&record);
+        assert_eq!(headers.count(), 3);
+        assert_eq!(headers.get(0).key, "trace-id");
+        assert_eq!(headers.get(0).value, Some(&b"abc"[..]));
+        assert_eq!(headers.get(1).value, None);
+        assert_eq!(headers.get(2).value, Some(&[0x00, 0xff, 0xfe, 0x41][..]));
+    }
+}
@@ -0,0 +1,502 @@
+use super::{Sink, SinkError};
+use crate::inflight::InFlight;
+use crate::record::MessageRecord;
+use arrow_array::{
+    ArrayRef, BinaryArray, Int32Array, Int64Array, RecordBatch, StringArray,
+    TimestampMicrosecondArray,
+};
+use arrow_schema::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDate, Utc};
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant};
+
+pub const MANIFEST_FILE: &str = "_manifest.ndjson";
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ParquetSinkSettings {
+    /// Root directory; files go to `<dir>/topic=<topic>/date=<YYYY-MM-DD>/`.
+    pub dir: PathBuf,
+    /// Rows buffered in memory before they are written as one row group.
+    #[serde(default = "default_row_group_size")]
+    pub row_group_size: usize,
+    /// A file is closed once it holds this many rows.
+    #[serde(default = "default_max_rows_per_file")]
+    pub max_rows_per_file: usize,
+    /// Open files are closed, and so become readable, at least this often.
+    #[serde(default = "default_flush_interval_secs")]
+    pub flush_interval_secs: u64,
+}
+
+fn default_row_group_size() -> usize {
+    10_000
+}
+
+fn default_max_rows_per_file() -> usize {
+    1_000_000
+}
+
+fn default_flush_interval_secs() -> u64 {
+    300
+}
+
+impl ParquetSinkSettings {
+    pub fn validate(&self) -> Result<(), Box<dyn std::error::Error>> {
+        if self.row_group_size == 0 {
+            return Err("sinks: parquet row_group_size must be greater than 0".into());
+        }
+        if self.max_rows_per_file < self.row_group_size {
+            return Err("sinks: parquet max_rows_per_file must be at least row_group_size".into());
+        }
+        if self.flush_interval_secs == 0 {
+            return Err("sinks: parquet flush_interval_secs must be greater than 0".into());
+        }
+        Ok(())
+    }
+}
+
+/// One line of `_manifest.ndjson`, written when a file is closed. Together the
+/// entries say which offsets of which Kafka partitions are archived where.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ManifestEntry {
+    /// Path relative to the sink's `dir`.
+    pub file: String,
+    pub topic: String,
+    pub date: NaiveDate,
+    pub rows: usize,
+    pub row_groups: usize,
+    pub offsets: Vec<OffsetRange>,
+    pub closed_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct OffsetRange {
+    pub partition: i32,
+    pub first_offset: i64,
+    pub last_offset: i64,
+    pub count: u64,
+}
+
+fn schema() -> SchemaRef {
+    let timestamp = DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into()));
+    Arc::new(Schema::new(vec![
+        Field::new("topic", DataType::Utf8, false),
+        Field::new("partition", DataType::Int32, false),
+        Field::new("offset", DataType::Int64, false),
+        Field::new("key", DataType::Binary, true),
+        Field::new("headers", DataType::Utf8, false),
+        Field::new("kafka_timestamp", timestamp.clone(), true),
+        Field::new("produced_at", timestamp.clone(), true),
+        Field::new("consumed_at", timestamp, false),
+        Field::new("event_id", DataType::Utf8, true),
+        Field::new("content_type", DataType::Utf8, false),
+        Field::new("payload_json", DataType::Utf8, true),
+        Field::new("payload_bytes", DataType::Binary, true),
+    ]))
+}
+
+fn to_batch(schema: &SchemaRef, rows: &[MessageRecord]) -> Result<RecordBatch, SinkError> {
+    let micros = |t: Option<DateTime<Utc>>| t.map(|t| t.timestamp_micros());
+    let timestamps = |values: Vec<Option<i64>>| {
+        Arc::new(TimestampMicrosecondArray::from(values).with_timezone("UTC")) as ArrayRef
+    };
+    let event_ids: Vec<Option<String>> = rows
+        .iter()
+        .map(|r| r.event_id().map(|id| id.to_string()))
+        .collect();
+    let payload_json: Vec<Option<String>> = rows.iter().map(|r| r.payload.json_text()).collect();
+
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(StringArray::from_iter_values(rows.iter().map(|r| &r.topic))),
+        Arc::new(Int32Array::from_iter_values(
+            rows.iter().map(|r| r.partition),
+        )),
+        Arc::new(Int64Array::from_iter_values(rows.iter().map(|r| r.offset))),
+        Arc::new(BinaryArray::from_iter(
+            rows.iter().map(|r| r.key.as_deref()),
+        )),
+        Arc::new(StringArray::from_iter_values(
+            rows.iter().map(|r| r.headers.to_string()),
+        )),
+        timestamps(rows.iter().map(|r| micros(r.kafka_timestamp)).collect()),
+        timestamps(rows.iter().map(|r| micros(r.produced_at())).collect()),
+        timestamps(
+            rows.iter()
+                .map(|r| Some(r.consumed_at.timestamp_micros()))
+                .collect(),
+        ),
+        Arc::new(StringArray::from(event_ids)),
+        Arc::new(StringArray::from_iter_values(
+            rows.iter().map(|r| &r.content_type),
+        )),
+        Arc::new(StringArray::from(payload_json)),
+        Arc::new(BinaryArray::from_iter(
+            rows.iter().map(|r| r.payload.as_bytes()),
+        )),
+    ];
+    Ok(RecordBatch::try_new(schema.clone(), columns)?)
+}
+
+/// The day a record is filed under: its Kafka timestamp, or when it was consumed.
+fn record_date(record: &MessageRecord) -> NaiveDate {
+    record
+        .kafka_timestamp
+        .unwrap_or(record.consumed_at)
+        .date_naive()
+}
+
+struct OpenFile {
+    writer: ArrowWriter<File>,
+    /// Written as `<file>.inprogress` and renamed when closed.
+    path: PathBuf,
+    relative_path: String,
+    pending: Vec<MessageRecord>,
+    rows: usize,
+    row_groups: usize,
+    offsets: BTreeMap<i32, OffsetRange>,
+    /// The first offset of each partition in the file, held back from commits
+    /// until the file is closed.
+    held: Vec<(i32, i64)>,
+    opened_at: Instant,
+}
+
+type FileKey = (String, NaiveDate);
+
+struct Inner {
+    settings: ParquetSinkSettings,
+    schema: SchemaRef,
+    in_flight: Arc<InFlight>,
+    files: Mutex<HashMap<FileKey, OpenFile>>,
+}
+
+/// Buffers records and writes them to rolling, snappy-compressed Parquet files
+/// partitioned by topic and date. Closed files are listed in `_manifest.ndjson`.
+/// A record's offset is only committed once the file holding it is closed.
+pub struct ParquetSink {
+    inner: Arc<Inner>,
+}
+
+impl ParquetSink {
+    /// Must be called within a Tokio runtime; spawns the flush timer.
+    pub fn new(
+        settings: ParquetSinkSettings,
+        in_flight: Arc<InFlight>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        fs::create_dir_all(&settings.dir)?;
+        let interval = Duration::from_secs(settings.flush_interval_secs);
+        let inner = Arc::new(Inner {
+            settings,
+            schema: schema(),
+            in_flight,
+            files: Mutex::new(HashMap::new()),
+        });
+        tokio::spawn(close_expired_files(Arc::downgrade(&inner), interval));
+        Ok(Self { inner })
+    }
+}
+
+async fn close_expired_files(inner: Weak<Inner>, interval: Duration) {
+    let mut ticker = tokio::time::interval(interval / 4);
+    loop {
+        ticker.tick().await;
+        let Some(inner) = inner.upgrade() else {
+            return;
+        };
+        let closed = tokio::task::spawn_blocking(move || {
+            inner.close_where(|file| file.opened_at.elapsed() >= interval)
+        })
+        .await;
+        match closed {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => log::error!("Failed to close expired parquet files: {}", e),
+            Err(e) => log::error!("Parquet close task failed: {}", e),
+        }
+    }
+}
+
+impl Inner {
+    fn open(&self, topic: &str, date: NaiveDate) -> Result<OpenFile, SinkError> {
+        let dir = Path::new(&format!("topic={}", topic)).join(format!("date={}", date));
+        fs::create_dir_all(self.settings.dir.join(&dir))?;
+        let name = format!("part-{}.parquet", Utc::now().format("%Y%m%dT%H%M%S%.6fZ"));
+        let relative_path = dir.join(name).to_string_lossy().into_owned();
+        let path = self.settings.dir.join(&relative_path);
+        let file = File::create(path.with_extension("parquet.inprogress"))?;
+        let props = WriterProperties::builder()
+            .set_max_row_group_size(self.settings.row_group_size)
+            .set_compression(Compression::SNAPPY)
+            .build();
+        Ok(OpenFile {
+            writer: ArrowWriter::try_new(file, self.schema.clone(), Some(props))?,
+            path,
+            relative_path,
+            pending: Vec::with_capacity(self.settings.row_group_size),
+            rows: 0,
+            row_groups: 0,
+            offsets: BTreeMap::new(),
+            held: Vec::new(),
+            opened_at: Instant::now(),
+        })
+    }
+
+    /// Leaves `pending` untouched unless the rows reached the writer.
+    fn write_row_group(&self, file: &mut OpenFile) -> Result<(), SinkError> {
+        if file.pending.is_empty() {
+            return Ok(());
+        }
+        let batch = to_batch(&self.schema, &file.pending)?;
+        file.writer.write(&batch)?;
+        file.pending.clear();
+        file.row_groups += 1;
+        file.writer.flush()?;
+        Ok(())
+    }
+
+    /// Finishes `file` and releases its offsets for commit. If this fails, the
+    /// offsets stay held, so the records are redelivered after a restart.
+    fn close(&self, (topic, date): FileKey, mut file: OpenFile) -> Result<(), SinkError> {
+        self.write_row_group(&mut file)?;
+        file.writer.close()?;
+        fs::rename(file.path.with_extension("parquet.inprogress"), &file.path)?;
+
+        let entry = ManifestEntry {
+            file: file.relative_path,
+            topic,
+            date,
+            rows: file.rows,
+            row_groups: file.row_groups,
+            offsets: file.offsets.into_values().collect(),
+            closed_at: Utc::now(),
+        };
+        let mut manifest = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.settings.dir.join(MANIFEST_FILE))?;
+        writeln!(manifest, "{}", serde_json::to_string(&entry)?)?;
+        log::info!("Archived {} rows to {}", entry.rows, entry.file);
+        for (partition, offset) in file.held {
+            self.in_flight.release(&entry.topic, partition, offset);
+        }
+        Ok(())
+    }
+
+    /// Closes every matching file, then reports the ones that failed.
+    fn close_where(&self, predicate: impl Fn(&OpenFile) -> bool) -> Result<(), SinkError> {
+        let mut files = self.files.lock().unwrap();
+        let keys: Vec<FileKey> = files
+            .iter()
+            .filter(|(_, file)| predicate(file))
+            .map(|(key, _)| key.clone())
+            .collect();
+        let mut errors = Vec::new();
+        for key in keys {
+            let file = files.remove(&key).expect("key collected above");
+            if let Err(e) = self.close(key.clone(), file) {
+                errors.push(format!("topic={}/date={}: {}", key.0, key.1, e));
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors.join("; ").into())
+        }
+    }
+
+    /// Either buffers `record` or fails without keeping any of it, so a retry
+    /// never writes a row twice.
+    fn write(&self, record: &MessageRecord) -> Result<(), SinkError> {
+        let key = (record.topic.clone(), record_date(record));
+        let mut files = self.files.lock().unwrap();
+        if files
+            .get(&key)
+            .is_some_and(|file| file.rows >= self.settings.max_rows_per_file)
+        {
+            let file = files.remove(&key).expect("present above");
+            self.close(key.clone(), file)?;
+        }
+        if !files.contains_key(&key) {
+            let file = self.open(&key.0, key.1)?;
+            files.insert(key.clone(), file);
+        }
+        let file = files.get_mut(&key).expect("inserted above");
+        if file.pending.len() >= self.settings.row_group_size {
+            self.write_row_group(file)?;
+        }
+
+        if !file.offsets.contains_key(&record.partition) {
+            self.in_flight
+                .hold(&record.topic, record.partition, record.offset);
+            file.held.push((record.partition, record.offset));
+        }
+        file.pending.push(record.clone());
+        file.rows += 1;
+        file.offsets
+            .entry(record.partition)
+            .and_modify(|range| {
+                range.first_offset = range.first_offset.min(record.offset);
+                range.last_offset = range.last_offset.max(record.offset);
+                range.count += 1;
+            })
+            .or_insert(OffsetRange {
+                partition: record.partition,
+                first_offset: record.offset,
+                last_offset: record.offset,
+                count: 1,
+            });
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Sink for ParquetSink {
+    fn name(&self) -> &str {
+        "parquet"
+    }
+
+    /// Succeeds once the record is buffered. Its offset is held back from
+    /// commits until its file is closed; see the flush interval.
+    async fn write(&self, record: &MessageRecord) -> Result<(), SinkError> {
+        let inner = self.inner.clone();
+        let record = record.clone();
+        tokio::task::spawn_blocking(move || inner.write(&record)).await?
+    }
+
+    async fn flush(&self) -> Result<(), SinkError> {
+        let inner = self.inner.clone();
+        tokio::task::spawn_blocking(move || inner.close_where(|_| true)).await?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Days;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use prometheus::Registry;
+
+    fn settings(dir: &Path) -> ParquetSinkSettings {
+        ParquetSinkSettings {
+            dir: dir.to_path_buf(),
+            row_group_size: 2,
+            max_rows_per_file: 4,
+            flush_interval_secs: default_flush_interval_secs(),
+        }
+    }
+
+    /// Writes `record` the way a worker does, so its offset is tracked.
+    async fn process(sink: &ParquetSink, in_flight: &InFlight, record: MessageRecord) {
+        let ticket = in_flight.admit(&record.topic, record.partition).unwrap();
+        assert!(in_flight.start(&record.topic, record.partition, ticket));
+        sink.write(&record).await.unwrap();
+        in_flight.finish(&record.topic, record.partition, ticket, record.offset, true);
+    }
+
+    fn manifest(dir: &Path) -> Vec<ManifestEntry> {
+        fs::read_to_string(dir.join(MANIFEST_FILE))
+            .unwrap()
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn validates_settings() {
+        let dir = Path::new("/tmp/archive");
+        assert!(settings(dir).validate().is_ok());
+        let too_small = ParquetSinkSettings {
+            max_rows_per_file: 1,
+            ..settings(dir)
+        };
+        assert!(too_small.validate().is_err());
+    }
+
+    #[tokio::test]
+    async fn rolls_files_and_records_offsets_in_manifest() {
+        let dir = tempfile::tempdir().unwrap();
+        let in_flight = InFlight::new(10, &Registry::new()).unwrap();
+        let sink = ParquetSink::new(settings(dir.path()), in_flight.clone()).unwrap();
+        let date = Utc::now().date_naive();
+
+        for offset in 10..15 {
+            process(&sink, &in_flight, MessageRecord::sample(offset)).await;
+        }
+        // The first four rows filled a file; the fifth is still buffered.
+        let entries = manifest(dir.path());
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].rows, 4);
+        assert_eq!(entries[0].row_groups, 2);
+        assert_eq!(entries[0].date, date);
+        assert_eq!(
+            entries[0].offsets,
+            vec![OffsetRange {
+                partition: 0,
+                first_offset: 10,
+                last_offset: 13,
+                count: 4,
+            }]
+        );
+        assert!(entries[0]
+            .file
+            .starts_with(&format!("topic=default-topic/date={}/part-", date)));
+
+        // Only the closed file's offsets may be committed.
+        assert_eq!(in_flight.committable("default-topic", 0), Some(14));
+
+        sink.flush().await.unwrap();
+        assert_eq!(in_flight.committable("default-topic", 0), Some(15));
+        let entries = manifest(dir.path());
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[1].offsets[0].first_offset, 14);
+
+        let file = File::open(dir.path().join(&entries[0].file)).unwrap();
+        let builder = ParquetRecordBatchReaderBuilder::try_new(file).unwrap();
+        assert_eq!(builder.metadata().num_row_groups(), 2);
+        let batches: Vec<RecordBatch> = builder.build().unwrap().map(Result::unwrap).collect();
+        let rows: usize = batches.iter().map(|b| b.num_rows()).sum();
+        assert_eq!(rows, 4);
+        let payload = batches[0]
+            .column_by_name("payload_json")
+            .unwrap()
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap()
+            .value(0)
+            .to_string();
+        assert_eq!(payload, r#"{"name":"Bob"}"#);
+    }
+
+    #[tokio::test]
+    async fn closes_every_file_and_keeps_offsets_of_failed_ones() {
+        let dir = tempfile::tempdir().unwrap();
+        let in_flight = InFlight::new(10, &Registry::new()).unwrap();
+        let sink = ParquetSink::new(settings(dir.path()), in_flight.clone()).unwrap();
+        let today = Utc::now();
+        let yesterday = today - Days::new(1);
+
+        for (offset, timestamp) in [(20, yesterday), (21, today)] {
+            let mut record = MessageRecord::sample(offset);
+            record.kafka_timestamp = Some(timestamp);
+            process(&sink, &in_flight, record).await;
+        }
+        // Yesterday's file can no longer be renamed into place.
+        let failing = dir
+            .path()
+            .join("topic=default-topic")
+            .join(format!("date={}", yesterday.date_naive()));
+        fs::remove_dir_all(&failing).unwrap();
+
+        assert!(sink.flush().await.is_err());
+        let entries = manifest(dir.path());
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].date, today.date_naive());
+        assert_eq!(in_flight.committable("default-topic", 0), Some(20));
+    }
+}
@@ -0,0 +1,55 @@
+use super::{Sink, SinkError};
+use crate::breaker::{BreakerState, CircuitBreaker};
+use crate::db::{self, Pool};
+use crate::record::MessageRecord;
+use async_trait::async_trait;
+use std::sync::Arc;
+
+/// Inserts records into the `messages` table. This is the default sink.
+pub struct PostgresSink {
+    pool: Pool,
+    breaker: Arc<CircuitBreaker>,
+}
+
+impl PostgresSink {
+    pub fn new(pool: Pool, breaker: Arc<CircuitBreaker>) -> Self {
+        Self { pool, breaker }
+    }
+}
+
+#[async_trait]
+impl Sink for PostgresSink {
+    fn name(&self) -> &str {
+        "postgres"
+    }
+
+    fn counts_as_db_insert(&self) -> bool {
+        true
+    }
+
+    /// While the circuit is open the record is held rather than failed, so it
+    /// is written (and its offset stored) once Postgres is back.
+    async fn write(&self, record: &MessageRecord) -> Result<(), SinkError> {
+        loop {
+            self.breaker.wait_until_closed().await;
+            match db::insert_message(&self.pool, record).await {
+                Ok(()) => {
+                    self.breaker.record_success();
+                    return Ok(());
+                }
+                Err(e) if db::is_unavailable(e.as_ref()) => {
+                    self.breaker.record_failure();
+                    if self.breaker.state() == BreakerState::Closed {
+                        return Err(e);
+                    }
+                    log::warn!(
+                        "Postgres unavailable; holding message at offset {} until it recovers: {}",
+                        record.offset,
+                        e
+                    );
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
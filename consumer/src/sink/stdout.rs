@@ -0,0 +1,21 @@
+use super::{Sink, SinkError};
+use crate::record::MessageRecord;
+use async_trait::async_trait;
+use std::io::Write;
+
+/// Prints each record as one JSON line on stdout (logs go to stderr).
+pub struct StdoutSink;
+
+#[async_trait]
+impl Sink for StdoutSink {
+    fn name(&self) -> &str {
+        "stdout"
+    }
+
+    async fn write(&self, record: &MessageRecord) -> Result<(), SinkError> {
+        let mut stdout = std::io::stdout().lock();
+        writeln!(stdout, "{}", record.to_json())?;
+        stdout.flush()?;
+        Ok(())
+    }
+}
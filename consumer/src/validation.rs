@@ -0,0 +1,291 @@
+use crate::db::{self, Pool};
+use crate::record::MessageRecord;
+use crate::sink::{KafkaSink, KafkaSinkSettings, Sink, SinkError};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use terrarium_core::kafka_security::KafkaSecurity;
+
+/// Errors kept per rejected record; the first one also determines the reason.
+const MAX_ERRORS: usize = 10;
+
+/// Where a topic's JSON Schema comes from.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub enum SchemaSource {
+    File(PathBuf),
+    Inline(Value),
+}
+
+impl SchemaSource {
+    fn load(&self) -> Result<Value, Box<dyn std::error::Error>> {
+        match self {
+            SchemaSource::File(path) => {
+                let text = std::fs::read_to_string(path)
+                    .map_err(|e| format!("failed to read schema {}: {}", path.display(), e))?;
+                Ok(serde_json::from_str(&text)?)
+            }
+            SchemaSource::Inline(schema) => Ok(schema.clone()),
+        }
+    }
+}
+
+/// Where records that fail validation go instead of the sinks.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum QuarantineSettings {
+    /// The `quarantined_messages` table; requires the `database` section.
+    #[default]
+    Table,
+    /// Another Kafka topic, with an `x-quarantine-reason` header added.
+    Topic(KafkaSinkSettings),
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ValidationSettings {
+    /// JSON Schemas by topic; records on other topics are not validated.
+    #[serde(default)]
+    pub schemas: HashMap<String, SchemaSource>,
+    #[serde(default)]
+    pub quarantine: QuarantineSettings,
+}
+
+impl ValidationSettings {
+    pub fn validate(&self, source_topic: &str) -> Result<(), Box<dyn std::error::Error>> {
+        if let QuarantineSettings::Topic(settings) = &self.quarantine {
+            settings.validate(source_topic)?;
+        }
+        Ok(())
+    }
+
+    pub fn needs_database(&self) -> bool {
+        !self.schemas.is_empty() && matches!(self.quarantine, QuarantineSettings::Table)
+    }
+}
+
+/// Why a record was rejected. `reason` is `not_json` or the JSON Schema keyword
+/// of the first violation (e.g. `required`, `type`).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rejection {
+    pub reason: String,
+    pub errors: Vec<String>,
+}
+
+pub struct SchemaValidator {
+    schemas: HashMap<String, jsonschema::Validator>,
+}
+
+impl SchemaValidator {
+    pub fn new(settings: &ValidationSettings) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut schemas = HashMap::new();
+        for (topic, source) in &settings.schemas {
+            let schema = source.load()?;
+            // Without this, `format` keywords such as `uuid` are not checked.
+            let validator = jsonschema::options()
+                .should_validate_formats(true)
+                .build(&schema)
+                .map_err(|e| format!("invalid JSON Schema for topic {}: {}", topic, e))?;
+            log::info!("Validating messages on {} against a JSON Schema", topic);
+            schemas.insert(topic.clone(), validator);
+        }
+        Ok(Self { schemas })
+    }
+
+    pub fn check(&self, record: &MessageRecord) -> Result<(), Rejection> {
+        let Some(validator) = self.schemas.get(&record.topic) else {
+            return Ok(());
+        };
+        let Some(instance) = record.payload.as_json() else {
+            return Err(Rejection {
+                reason: "not_json".to_string(),
+                errors: vec![format!("payload is not JSON ({})", record.content_type)],
+            });
+        };
+
+        let mut reason = None;
+        let errors: Vec<String> = validator
+            .iter_errors(instance)
+            .take(MAX_ERRORS)
+            .map(|e| {
+                reason.get_or_insert_with(|| schema_keyword(e.schema_path.as_str()));
+                let path = e.instance_path.as_str();
+                format!("{}: {}", if path.is_empty() { "/" } else { path }, e)
+            })
+            .collect();
+        match reason {
+            None => Ok(()),
+            Some(reason) => Err(Rejection { reason, errors }),
+        }
+    }
+}
+
+/// The keyword is the last segment of the schema path, e.g.
+/// `/properties/name/type` -> `type`.
+fn schema_keyword(schema_path: &str) -> String {
+    match schema_path.rsplit('/').next() {
+        Some(keyword) if !keyword.is_empty() => keyword.to_string(),
+        _ => "schema".to_string(),
+    }
+}
+
+pub enum Quarantine {
+    Table(Pool),
+    Topic(KafkaSink),
+}
+
+impl Quarantine {
+    pub fn new(
+        settings: &QuarantineSettings,
+        pool: Option<&Pool>,
+        kafka_broker: &str,
+        kafka_security: &KafkaSecurity,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(match settings {
+            QuarantineSettings::Table => Quarantine::Table(
+                pool.ok_or("the quarantine table requires a database section")?
+                    .clone(),
+            ),
+            QuarantineSettings::Topic(settings) => Quarantine::Topic(KafkaSink::new(
+                settings.clone(),
+                kafka_broker,
+                kafka_security,
+            )?),
+        })
+    }
+
+    pub async fn write(
+        &self,
+        record: &MessageRecord,
+        rejection: &Rejection,
+    ) -> Result<(), SinkError> {
+        match self {
+            Quarantine::Table(pool) => db::insert_quarantined(pool, record, rejection).await,
+            Quarantine::Topic(sink) => sink.write(&with_reason_header(record, rejection)).await,
+        }
+    }
+}
+
+fn with_reason_header(record: &MessageRecord, rejection: &Rejection) -> MessageRecord {
+    let mut record = record.clone();
+    if let Some(headers) = record.headers.as_array_mut() {
+        headers.push(json!({"key": "x-quarantine-reason", "value": rejection.reason}));
+    }
+    record
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::record::Payload;
+
+    fn settings() -> ValidationSettings {
+        serde_json::from_value(json!({
+            "schemas": {
+                "default-topic": {"inline": {
+                    "type": "object",
+                    "required": ["name"],
+                    "properties": {"name": {"type": "string"}}
+                }}
+            }
+        }))
+        .unwrap()
+    }
+
+    fn record(payload: Payload) -> MessageRecord {
+        let mut record = MessageRecord::sample(1);
+        record.payload = payload;
+        record
+    }
+
+    #[test]
+    fn accepts_valid_and_unvalidated_records() {
+        let validator = SchemaValidator::new(&settings()).unwrap();
+        assert!(validator
+            .check(&record(Payload::json(json!({"name": "Bob"}))))
+            .is_ok());
+
+        let mut other = record(Payload::Bytes(b"anything".to_vec()));
+        other.topic = "other-topic".to_string();
+        assert!(validator.check(&other).is_ok());
+    }
+
+    #[test]
+    fn rejects_with_schema_keyword_as_reason() {
+        let validator = SchemaValidator::new(&settings()).unwrap();
+
+        let missing = validator
+            .check(&record(Payload::json(json!({}))))
+            .unwrap_err();
+        assert_eq!(missing.reason, "required");
+        assert_eq!(missing.errors.len(), 1);
+        assert!(missing.errors[0].starts_with("/: "));
+
+        let wrong_type = validator
+            .check(&record(Payload::json(json!({"name": 7}))))
+            .unwrap_err();
+        assert_eq!(wrong_type.reason, "type");
+        assert!(wrong_type.errors[0].starts_with("/name: "));
+
+        let text = validator
+            .check(&record(Payload::Bytes(b"hi".to_vec())))
+            .unwrap_err();
+        assert_eq!(text.reason, "not_json");
+    }
+
+    #[test]
+    fn bundled_schema_accepts_hello_events() {
+        let settings: ValidationSettings = serde_json::from_value(json!({
+            "schemas": {"default-topic": {"file": concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/schemas/hello_event.json"
+            )}}
+        }))
+        .unwrap();
+        let validator = SchemaValidator::new(&settings).unwrap();
+        let event = json!({
+            "event_id": "0f8fad5b-d9cb-469f-a165-70867728950e",
+            "name": "Bob",
+            "produced_at": "2026-01-01T00:00:00Z"
+        });
+        assert!(validator.check(&record(Payload::json(event))).is_ok());
+        // Older API versions sent no event_id, and names may be empty.
+        let legacy = json!({"name": "", "produced_at": "2026-01-01T00:00:00Z"});
+        assert!(validator.check(&record(Payload::json(legacy))).is_ok());
+        let rejection = validator
+            .check(&record(Payload::json(json!({"name": "Bob"}))))
+            .unwrap_err();
+        assert_eq!(rejection.reason, "required");
+        let malformed = json!({"name": "Bob", "produced_at": "yesterday"});
+        let rejection = validator
+            .check(&record(Payload::json(malformed)))
+            .unwrap_err();
+        assert_eq!(rejection.reason, "format");
+    }
+
+    #[test]
+    fn parses_quarantine_settings() {
+        assert!(settings().needs_database());
+        let topic: ValidationSettings = serde_json::from_value(json!({
+            "schemas": {"default-topic": {"file": "schemas/hello.json"}},
+            "quarantine": {"type": "topic", "topic": "hello-quarantine"}
+        }))
+        .unwrap();
+        assert!(!topic.needs_database());
+        topic.validate("default-topic").unwrap();
+        assert!(SchemaValidator::new(&topic).is_err());
+    }
+
+    #[test]
+    fn adds_reason_header_for_quarantine_topic() {
+        let rejection = Rejection {
+            reason: "required".to_string(),
+            errors: vec![],
+        };
+        let quarantined = with_reason_header(&record(Payload::json(json!({}))), &rejection);
+        assert_eq!(
+            quarantined.headers,
+            json!([{"key": "x-quarantine-reason", "value": "required"}])
+        );
+    }
+}
@@ -0,0 +1,280 @@
+use crate::inflight::{InFlight, Ticket};
+use rdkafka::message::{Message, OwnedMessage};
+use serde::Deserialize;
+use std::collections::hash_map::DefaultHasher;
+use std::future::Future;
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ProcessingSettings {
+    /// Worker tasks processing messages concurrently. All messages of one
+    /// partition go to the same worker, so in-partition order is preserved.
+    pub workers: usize,
+    /// Messages received but not yet processed, per partition. A partition is
+    /// paused while it is at the limit; the others keep being polled.
+    pub max_in_flight_per_partition: usize,
+}
+
+impl Default for ProcessingSettings {
+    fn default() -> Self {
+        Self {
+            workers: 4,
+            max_in_flight_per_partition: 500,
+        }
+    }
+}
+
+impl ProcessingSettings {
+    pub fn validate(&self) -> Result<(), Box<dyn std::error::Error>> {
+        if self.workers == 0 {
+            return Err("processing.workers must be greater than 0".into());
+        }
+        if self.max_in_flight_per_partition == 0 {
+            return Err("processing.max_in_flight_per_partition must be greater than 0".into());
+        }
+        Ok(())
+    }
+}
+
+fn worker_for(topic: &str, partition: i32, workers: usize) -> usize {
+    let mut hasher = DefaultHasher::new();
+    (topic, partition).hash(&mut hasher);
+    (hasher.finish() % workers as u64) as usize
+}
+
+struct Job {
+    message: OwnedMessage,
+    ticket: Ticket,
+}
+
+/// Fans messages out to keyed worker tasks.
+pub struct WorkerPool {
+    senders: Vec<mpsc::UnboundedSender<Job>>,
+    workers: Vec<JoinHandle<()>>,
+    in_flight: Arc<InFlight>,
+}
+
+impl WorkerPool {
+    /// Spawns the workers; each runs `handler` on its messages one at a time.
+    /// `handler` returns false for a message that must not be committed.
+    pub fn spawn<F, Fut>(
+        settings: &ProcessingSettings,
+        in_flight: Arc<InFlight>,
+        handler: F,
+    ) -> Self
+    where
+        F: Fn(OwnedMessage) -> Fut + Clone + Send + 'static,
+        Fut: Future<Output = bool> + Send,
+    {
+        let (senders, workers) = (0..settings.workers)
+            .map(|_| {
+                let (tx, mut rx) = mpsc::unbounded_channel::<Job>();
+                let handler = handler.clone();
+                let in_flight = in_flight.clone();
+                let worker = tokio::spawn(async move {
+                    while let Some(Job { message, ticket }) = rx.recv().await {
+                        let (topic, partition, offset) = (
+                            message.topic().to_string(),
+                            message.partition(),
+                            message.offset(),
+                        );
+                        if !in_flight.start(&topic, partition, ticket) {
+                            continue;
+                        }
+                        let committable = handler(message).await;
+                        in_flight.finish(&topic, partition, ticket, offset, committable);
+                    }
+                });
+                (tx, worker)
+            })
+            .unzip();
+        log::info!(
+            "Processing with {} workers and at most {} messages in flight per partition",
+            settings.workers,
+            settings.max_in_flight_per_partition
+        );
+        Self {
+            senders,
+            workers,
+            in_flight,
+        }
+    }
+
+    /// Queues `message` on its partition's worker without waiting. Returns
+    /// true once the partition is backed up or stalled and should be paused.
+    pub fn dispatch(&self, message: OwnedMessage) -> bool {
+        let (topic, partition) = (message.topic().to_string(), message.partition());
+        let Some(ticket) = self.in_flight.admit(&topic, partition) else {
+            // Not stored, so it is redelivered once the partition moves on.
+            log::debug!(
+                "Dropping message for stalled partition {}[{}]",
+                topic,
+                partition
+            );
+            return true;
+        };
+        let worker = worker_for(&topic, partition, self.senders.len());
+        if self.senders[worker].send(Job { message, ticket }).is_err() {
+            log::error!("Worker {} stopped; dropping message", worker);
+            self.in_flight.skip(&topic, partition, ticket);
+        }
+        self.in_flight.is_blocked(&topic, partition)
+    }
+
+    /// Stops accepting messages and waits for queued ones to be processed.
+    pub async fn shutdown(self) {
+        drop(self.senders);
+        for worker in self.workers {
+            if let Err(e) = worker.await {
+                log::error!("Worker task failed: {}", e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::breaker::{BreakerSettings, CircuitBreaker};
+    use prometheus::Registry;
+    use rdkafka::message::Timestamp;
+    use std::collections::HashSet;
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    fn message(partition: i32, offset: i64) -> OwnedMessage {
+        OwnedMessage::new(
+            Some(b"{}".to_vec()),
+            None,
+            "default-topic".to_string(),
+            Timestamp::NotAvailable,
+            partition,
+            offset,
+            None,
+        )
+    }
+
+    #[test]
+    fn routes_each_partition_to_one_worker() {
+        for partition in 0..32 {
+            let worker = worker_for("default-topic", partition, 4);
+            assert!(worker < 4);
+            assert_eq!(worker, worker_for("default-topic", partition, 4));
+        }
+        assert_eq!(worker_for("default-topic", 7, 1), 0);
+    }
+
+    #[test]
+    fn validates_settings() {
+        assert!(ProcessingSettings::default().validate().is_ok());
+        let settings = ProcessingSettings {
+            workers: 8,
+            max_in_flight_per_partition: 0,
+        };
+        assert!(settings.validate().is_err());
+    }
+
+    #[tokio::test]
+    async fn keeps_dispatching_while_the_circuit_is_open() {
+        let registry = Registry::new();
+        let breaker = CircuitBreaker::new(
+            BreakerSettings {
+                failure_threshold: 1,
+                ..Default::default()
+            },
+            &registry,
+        )
+        .unwrap();
+        breaker.record_failure();
+        let in_flight = InFlight::new(2, &registry).unwrap();
+        let pool = WorkerPool::spawn(
+            &ProcessingSettings {
+                workers: 2,
+                max_in_flight_per_partition: 2,
+            },
+            in_flight.clone(),
+            {
+                let breaker = breaker.clone();
+                move |_| {
+                    let breaker = breaker.clone();
+                    async move {
+                        breaker.wait_until_closed().await;
+                        true
+                    }
+                }
+            },
+        );
+
+        // Every message is held by the open circuit, yet dispatching returns
+        // at once and only the backed-up partition asks to be paused.
+        for offset in 0..5 {
+            pool.dispatch(message(0, offset));
+        }
+        tokio::task::yield_now().await;
+        assert!(!pool.dispatch(message(1, 0)));
+        assert_eq!(
+            in_flight.blocked(),
+            HashSet::from([("default-topic".to_string(), 0)])
+        );
+
+        breaker.record_success();
+        tokio::time::timeout(Duration::from_secs(5), pool.shutdown())
+            .await
+            .unwrap();
+        assert!(in_flight.blocked().is_empty());
+        assert_eq!(in_flight.committable("default-topic", 0), Some(5));
+    }
+
+    #[tokio::test]
+    async fn preserves_order_within_each_partition() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let handler_seen = seen.clone();
+        let in_flight = InFlight::new(4, &Registry::new()).unwrap();
+        let pool = WorkerPool::spawn(
+            &ProcessingSettings {
+                workers: 3,
+                max_in_flight_per_partition: 4,
+            },
+            in_flight.clone(),
+            move |message: OwnedMessage| {
+                let seen = handler_seen.clone();
+                async move {
+                    // Later offsets finish faster, so only per-partition
+                    // sequencing keeps them in order.
+                    let delay = 10 - message.offset() as u64;
+                    tokio::time::sleep(Duration::from_millis(delay)).await;
+                    seen.lock()
+                        .unwrap()
+                        .push((message.partition(), message.offset()));
+                    true
+                }
+            },
+        );
+
+        for offset in 0..5 {
+            for partition in 0..3 {
+                // Dispatching never waits; the fifth message backs a partition up.
+                assert_eq!(pool.dispatch(message(partition, offset)), offset >= 3);
+            }
+        }
+        pool.shutdown().await;
+        for partition in 0..3 {
+            assert_eq!(in_flight.committable("default-topic", partition), Some(5));
+        }
+
+        let seen = seen.lock().unwrap();
+        assert_eq!(seen.len(), 15);
+        for partition in 0..3 {
+            let offsets: Vec<i64> = seen
+                .iter()
+                .filter(|(p, _)| *p == partition)
+                .map(|(_, o)| *o)
+                .collect();
+            assert_eq!(offsets, vec![0, 1, 2, 3, 4]);
+        }
+    }
+}
@@ -0,0 +1,52 @@
+-- Convert messages into a table range-partitioned by created_at. Existing rows
+-- move into messages_legacy, which covers everything up to the end of the day
+-- the migration runs; the consumer's maintenance task creates daily partitions
+-- from there on and records every partition in message_partitions.
+ALTER TABLE messages RENAME TO messages_unpartitioned;
+ALTER INDEX idx_messages_topic RENAME TO idx_messages_unpartitioned_topic;
+ALTER INDEX idx_messages_created_at RENAME TO idx_messages_unpartitioned_created_at;
+ALTER INDEX idx_messages_event_id RENAME TO idx_messages_unpartitioned_event_id;
+ALTER INDEX idx_messages_coordinates RENAME TO idx_messages_unpartitioned_coordinates;
+ALTER SEQUENCE messages_id_seq OWNED BY NONE;
+
+CREATE TABLE messages (
+    id INT NOT NULL DEFAULT nextval('messages_id_seq'),
+    topic VARCHAR(255) NOT NULL,
+    part INT NOT NULL,
+    kafkaoffset BIGINT NOT NULL,
+    payload TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    event_id UUID,
+    PRIMARY KEY (id, created_at)
+) PARTITION BY RANGE (created_at);
+
+ALTER SEQUENCE messages_id_seq OWNED BY messages.id;
+
+CREATE TABLE message_partitions (
+    name TEXT PRIMARY KEY,
+    range_start TIMESTAMPTZ,
+    range_end TIMESTAMPTZ NOT NULL
+);
+
+DO $$
+DECLARE
+    legacy_end TIMESTAMPTZ := (date_trunc('day', NOW() AT TIME ZONE 'UTC') + INTERVAL '1 day') AT TIME ZONE 'UTC';
+BEGIN
+    EXECUTE format(
+        'CREATE TABLE messages_legacy PARTITION OF messages FOR VALUES FROM (MINVALUE) TO (%L)',
+        legacy_end
+    );
+    INSERT INTO message_partitions (name, range_start, range_end)
+    VALUES ('messages_legacy', NULL, legacy_end);
+END $$;
+
+INSERT INTO messages (id, topic, part, kafkaoffset, payload, created_at, event_id)
+SELECT id, topic, part, kafkaoffset, payload, COALESCE(created_at, NOW()), event_id
+FROM messages_unpartitioned;
+
+DROP TABLE messages_unpartitioned;
+
+CREATE INDEX idx_messages_topic ON messages(topic);
+CREATE INDEX idx_messages_created_at ON messages(created_at);
+CREATE INDEX idx_messages_event_id ON messages(event_id);
+CREATE INDEX idx_messages_coordinates ON messages(topic, part, kafkaoffset);
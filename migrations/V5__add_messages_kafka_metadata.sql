@@ -0,0 +1,5 @@
+ALTER TABLE messages
+    ADD COLUMN message_key BYTEA,
+    ADD COLUMN headers JSONB NOT NULL DEFAULT '[]'::jsonb,
+    ADD COLUMN kafka_timestamp TIMESTAMPTZ,
+    ADD COLUMN produced_at TIMESTAMPTZ;
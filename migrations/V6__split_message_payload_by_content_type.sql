@@ -0,0 +1,27 @@
+-- JSON payloads are stored as JSONB so they can be queried with JSON path
+-- expressions; everything else keeps its exact bytes in payload_bytes.
+ALTER TABLE messages
+    ADD COLUMN payload_json JSONB,
+    ADD COLUMN payload_bytes BYTEA,
+    ADD COLUMN content_type TEXT NOT NULL DEFAULT 'application/octet-stream';
+
+CREATE FUNCTION pg_temp.try_parse_jsonb(input TEXT) RETURNS JSONB AS $$
+BEGIN
+    RETURN input::jsonb;
+EXCEPTION WHEN others THEN
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql IMMUTABLE;
+
+UPDATE messages SET payload_json = pg_temp.try_parse_jsonb(payload);
+UPDATE messages SET content_type = 'application/json' WHERE payload_json IS NOT NULL;
+UPDATE messages
+SET payload_bytes = convert_to(payload, 'UTF8'), content_type = 'text/plain'
+WHERE payload_json IS NULL;
+
+ALTER TABLE messages DROP COLUMN payload;
+ALTER TABLE messages
+    ADD CONSTRAINT messages_payload_present
+    CHECK (payload_json IS NOT NULL OR payload_bytes IS NOT NULL);
+
+CREATE INDEX idx_messages_payload_json ON messages USING GIN (payload_json jsonb_path_ops);
@@ -0,0 +1,6 @@
+-- Widen messages.id to BIGINT (BIGSERIAL semantics) before the int4 sequence
+-- runs out, and record when the consumer received each record.
+ALTER TABLE messages ALTER COLUMN id TYPE BIGINT;
+ALTER SEQUENCE messages_id_seq AS BIGINT;
+
+ALTER TABLE messages ADD COLUMN consumed_at TIMESTAMPTZ;
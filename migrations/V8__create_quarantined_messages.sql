@@ -0,0 +1,19 @@
+-- Records rejected by the consumer's schema validation, kept with the reason
+-- so they can be inspected and replayed.
+CREATE TABLE quarantined_messages (
+    id BIGSERIAL PRIMARY KEY,
+    topic VARCHAR(255) NOT NULL,
+    part INT NOT NULL,
+    kafkaoffset BIGINT NOT NULL,
+    message_key BYTEA,
+    headers JSONB NOT NULL DEFAULT '[]',
+    payload BYTEA NOT NULL,
+    content_type TEXT NOT NULL,
+    reason TEXT NOT NULL,
+    errors JSONB NOT NULL DEFAULT '[]',
+    kafka_timestamp TIMESTAMPTZ,
+    quarantined_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_quarantined_messages_topic_reason ON quarantined_messages(topic, reason);
+CREATE INDEX idx_quarantined_messages_coordinates ON quarantined_messages(topic, part, kafkaoffset);
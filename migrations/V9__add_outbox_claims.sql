@@ -0,0 +1,3 @@
+-- The outbox relay claims rows with a lease instead of holding row locks
+-- while it publishes them. An expired lease lets another relay take over.
+ALTER TABLE outbox ADD COLUMN claimed_until TIMESTAMPTZ;
@@ -0,0 +1,310 @@
+use crate::kafka_security::SecretSource;
+use crate::metrics;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request as HttpRequest, Response as HttpResponse, Server as HttpServer};
+use prometheus::Registry;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+
+/// Enables a binary's authenticated `/admin/*` endpoints.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AdminSettings {
+    /// Bearer token required by every `/admin/*` endpoint.
+    pub token: SecretSource,
+}
+
+impl AdminSettings {
+    /// Reads the token. An empty one is an error: it would let a bare
+    /// `Authorization: Bearer ` header through.
+    pub fn resolve_token(&self) -> Result<String, Box<dyn std::error::Error>> {
+        let token = self.token.resolve()?;
+        if token.trim().is_empty() {
+            return Err("admin.token must not be empty".into());
+        }
+        Ok(token)
+    }
+
+    pub fn validate(&self) -> Result<(), Box<dyn std::error::Error>> {
+        self.resolve_token().map(drop)
+    }
+}
+
+type ResponseFuture = Pin<Box<dyn Future<Output = HttpResponse<Body>> + Send>>;
+type Handler = Arc<dyn Fn(HttpRequest<Body>) -> ResponseFuture + Send + Sync>;
+
+struct Route {
+    method: Method,
+    path: String,
+    handler: Handler,
+}
+
+/// Admin HTTP server exposing `/metrics`, `/healthz` and `/dashboard`, plus any
+/// routes registered by the binary. Registered routes take precedence over the
+/// built-in ones, so a binary can e.g. replace `/healthz` with a deeper check.
+#[derive(Clone)]
+pub struct AdminServer {
+    title: String,
+    registry: Registry,
+    routes: Arc<Vec<Route>>,
+}
+
+impl AdminServer {
+    pub fn new(title: impl Into<String>, registry: Registry) -> Self {
+        Self {
+            title: title.into(),
+            registry,
+            routes: Arc::new(Vec::new()),
+        }
+    }
+
+    pub fn route<F, Fut>(mut self, method: Method, path: &str, handler: F) -> Self
+    where
+        F: Fn(HttpRequest<Body>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = HttpResponse<Body>> + Send + 'static,
+    {
+        let handler: Handler = Arc::new(move |req| Box::pin(handler(req)));
+        Arc::get_mut(&mut self.routes)
+            .expect("routes must be registered before the server is cloned")
+            .push(Route {
+                method,
+                path: path.to_string(),
+                handler,
+            });
+        self
+    }
+
+    pub async fn handle(&self, req: HttpRequest<Body>) -> Result<HttpResponse<Body>, hyper::Error> {
+        if let Some(route) = self
+            .routes
+            .iter()
+            .find(|r| r.method == req.method() && r.path == req.uri().path())
+        {
+            return Ok((route.handler)(req).await);
+        }
+
+        match (req.method(), req.uri().path()) {
+            (&Method::GET, "/metrics") => Ok(self.metrics()),
+            (&Method::GET, "/healthz") => Ok(HttpResponse::new(Body::from("ok"))),
+            (&Method::GET, "/dashboard") => Ok(html(self.dashboard())),
+            _ => Ok(text(404, "not found")),
+        }
+    }
+
+    /// Serves until the underlying hyper server fails.
+    pub async fn serve(self, addr: SocketAddr) -> Result<(), hyper::Error> {
+        log::info!("Starting {} HTTP server on {}", self.title, addr);
+        let make_svc = make_service_fn(move |_| {
+            let server = self.clone();
+            async move {
+                Ok::<_, hyper::Error>(service_fn(move |req: HttpRequest<Body>| {
+                    let server = server.clone();
+                    async move { server.handle(req).await }
+                }))
+            }
+        });
+
+        HttpServer::bind(&addr).serve(make_svc).await
+    }
+
+    fn metrics(&self) -> HttpResponse<Body> {
+        match metrics::encode_text(&self.registry) {
+            Ok((buffer, content_type)) => HttpResponse::builder()
+                .status(200)
+                .header("Content-Type", content_type)
+                .body(Body::from(buffer))
+                .unwrap(),
+            Err(e) => {
+                log::error!("Failed to encode metrics: {}", e);
+                text(500, "failed to encode metrics")
+            }
+        }
+    }
+
+    fn dashboard(&self) -> String {
+        format!(
+            "<html><head><title>{title}</title></head><body><h1>{title}</h1><p>Prometheus metrics are available at <a href=\"/metrics\">/metrics</a>.</p><p>Health check: <a href=\"/healthz\">/healthz</a></p></body></html>",
+            title = self.title
+        )
+    }
+}
+
+/// Decoded query string parameters; for repeated keys the last one wins.
+pub fn query_params(req: &HttpRequest<Body>) -> HashMap<String, String> {
+    let decode = |s: &str| {
+        percent_encoding::percent_decode_str(&s.replace('+', " "))
+            .decode_utf8_lossy()
+            .into_owned()
+    };
+    req.uri()
+        .query()
+        .unwrap_or_default()
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (decode(key), decode(value))
+        })
+        .collect()
+}
+
+/// Whether `req` carries `Authorization: Bearer <token>`. The comparison takes
+/// the same time wherever the tokens differ.
+pub fn bearer_authorized(req: &HttpRequest<Body>, token: &str) -> bool {
+    if token.trim().is_empty() {
+        return false;
+    }
+    let Some(presented) = req
+        .headers()
+        .get(hyper::header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+    else {
+        return false;
+    };
+    presented.len() == token.len()
+        && presented
+            .bytes()
+            .zip(token.bytes())
+            .fold(0u8, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+pub fn text(status: u16, body: impl Into<Body>) -> HttpResponse<Body> {
+    HttpResponse::builder()
+        .status(status)
+        .body(body.into())
+        .unwrap()
+}
+
+pub fn html(body: impl Into<Body>) -> HttpResponse<Body> {
+    HttpResponse::builder()
+        .status(200)
+        .header("Content-Type", "text/html; charset=utf-8")
+        .body(body.into())
+        .unwrap()
+}
+
+pub fn json<T: serde::Serialize>(status: u16, value: &T) -> HttpResponse<Body> {
+    match serde_json::to_vec(value) {
+        Ok(body) => HttpResponse::builder()
+            .status(status)
+            .header("Content-Type", "application/json")
+            .body(Body::from(body))
+            .unwrap(),
+        Err(e) => text(500, format!("failed to encode response: {}", e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::body::to_bytes;
+
+    fn get(path: &str) -> HttpRequest<Body> {
+        HttpRequest::builder()
+            .method(Method::GET)
+            .uri(path)
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    async fn body_string(resp: HttpResponse<Body>) -> String {
+        let bytes = to_bytes(resp.into_body()).await.unwrap();
+        String::from_utf8(bytes.to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn serves_builtin_routes() {
+        let server = AdminServer::new("Test Dashboard", Registry::new());
+
+        let resp = server.handle(get("/healthz")).await.unwrap();
+        assert_eq!(resp.status(), 200);
+        assert_eq!(body_string(resp).await, "ok");
+
+        let resp = server.handle(get("/dashboard")).await.unwrap();
+        assert!(body_string(resp).await.contains("Test Dashboard"));
+
+        let resp = server.handle(get("/metrics")).await.unwrap();
+        assert_eq!(resp.status(), 200);
+
+        let resp = server.handle(get("/nope")).await.unwrap();
+        assert_eq!(resp.status(), 404);
+    }
+
+    #[tokio::test]
+    async fn custom_routes_take_precedence() {
+        let server = AdminServer::new("Test", Registry::new())
+            .route(Method::GET, "/healthz", |_| async { text(503, "degraded") })
+            .route(Method::POST, "/echo", |req| async move {
+                let body = to_bytes(req.into_body()).await.unwrap();
+                text(200, body)
+            });
+
+        let resp = server.handle(get("/healthz")).await.unwrap();
+        assert_eq!(resp.status(), 503);
+
+        let req = HttpRequest::builder()
+            .method(Method::POST)
+            .uri("/echo")
+            .body(Body::from("hi"))
+            .unwrap();
+        let resp = server.handle(req).await.unwrap();
+        assert_eq!(body_string(resp).await, "hi");
+
+        // Method mismatch falls through to 404.
+        let resp = server.handle(get("/echo")).await.unwrap();
+        assert_eq!(resp.status(), 404);
+    }
+
+    #[test]
+    fn decodes_query_params() {
+        let params = query_params(&get(
+            "/api/messages?topic=a%2Fb&name=Bob+Smith&empty&limit=5",
+        ));
+        assert_eq!(params["topic"], "a/b");
+        assert_eq!(params["name"], "Bob Smith");
+        assert_eq!(params["empty"], "");
+        assert_eq!(params["limit"], "5");
+        assert!(query_params(&get("/metrics")).is_empty());
+    }
+
+    #[test]
+    fn checks_bearer_tokens() {
+        let with_header = |value: &str| {
+            HttpRequest::builder()
+                .header("Authorization", value)
+                .body(Body::empty())
+                .unwrap()
+        };
+        assert!(bearer_authorized(&with_header("Bearer s3cret"), "s3cret"));
+        assert!(!bearer_authorized(&with_header("Bearer s3cre"), "s3cret"));
+        assert!(!bearer_authorized(&with_header("Bearer s3creT"), "s3cret"));
+        assert!(!bearer_authorized(&with_header("Basic s3cret"), "s3cret"));
+        assert!(!bearer_authorized(&get("/"), "s3cret"));
+        assert!(!bearer_authorized(&with_header("Bearer "), ""));
+        assert!(!bearer_authorized(&with_header("Bearer  "), " "));
+    }
+
+    #[test]
+    fn rejects_empty_tokens() {
+        let settings = |var: &str| AdminSettings {
+            token: SecretSource::Env(var.to_string()),
+        };
+        std::env::set_var("TERRARIUM_TEST_EMPTY_TOKEN", "");
+        std::env::set_var("TERRARIUM_TEST_BLANK_TOKEN", " \t");
+        std::env::set_var("TERRARIUM_TEST_ADMIN_TOKEN", "s3cret");
+        assert!(settings("TERRARIUM_TEST_EMPTY_TOKEN").validate().is_err());
+        assert!(settings("TERRARIUM_TEST_BLANK_TOKEN").validate().is_err());
+        assert_eq!(
+            settings("TERRARIUM_TEST_ADMIN_TOKEN")
+                .resolve_token()
+                .unwrap(),
+            "s3cret"
+        );
+    }
+}
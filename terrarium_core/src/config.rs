@@ -0,0 +1,70 @@
+use log::info;
+use serde::de::DeserializeOwned;
+use std::fs;
+
+/// Reads raw config text. If `env_var` is set, its value is the config itself
+/// (e.g. `CONSUMER_CONFIG="$(cat config.json)"`); otherwise `default_path` is read.
+pub fn read_source(
+    env_var: &str,
+    default_path: &str,
+) -> Result<String, Box<dyn std::error::Error>> {
+    match std::env::var(env_var) {
+        Ok(contents) => {
+            info!("Loading config from ${}", env_var);
+            Ok(contents)
+        }
+        Err(_) => {
+            info!("Loading config from {}", default_path);
+            fs::read_to_string(default_path)
+                .map_err(|e| format!("failed to read config {}: {}", default_path, e).into())
+        }
+    }
+}
+
+/// Parses a JSON config and runs its validation hook.
+pub fn parse_json<T, F>(config_str: &str, validate: F) -> Result<T, Box<dyn std::error::Error>>
+where
+    T: DeserializeOwned,
+    F: FnOnce(&T) -> Result<(), Box<dyn std::error::Error>>,
+{
+    let config: T = serde_json::from_str(config_str)?;
+    validate(&config)?;
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, Deserialize)]
+    struct Sample {
+        name: String,
+    }
+
+    #[test]
+    fn reads_config_from_env_before_file() {
+        std::env::set_var("TERRARIUM_TEST_CONFIG", r#"{"name": "from-env"}"#);
+        let source = read_source("TERRARIUM_TEST_CONFIG", "/does/not/exist.json").unwrap();
+        let sample: Sample = parse_json(&source, |_| Ok(())).unwrap();
+        assert_eq!(sample.name, "from-env");
+    }
+
+    #[test]
+    fn reports_missing_file() {
+        let err = read_source("TERRARIUM_TEST_UNSET_CONFIG", "/does/not/exist.json").unwrap_err();
+        assert!(err.to_string().contains("/does/not/exist.json"));
+    }
+
+    #[test]
+    fn runs_validation_hook() {
+        let result: Result<Sample, _> = parse_json(r#"{"name": ""}"#, |s: &Sample| {
+            if s.name.is_empty() {
+                Err("name is required".into())
+            } else {
+                Ok(())
+            }
+        });
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,245 @@
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+/// Where a secret is read from. Secrets are never accepted inline in the config file.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub enum SecretSource {
+    Env(String),
+    File(PathBuf),
+}
+
+impl SecretSource {
+    pub fn resolve(&self) -> Result<String, Box<dyn std::error::Error>> {
+        match self {
+            SecretSource::Env(var) => std::env::var(var)
+                .map_err(|e| format!("failed to read secret from env var {}: {}", var, e).into()),
+            SecretSource::File(path) => std::fs::read_to_string(path)
+                .map(|s| s.trim_end_matches(['\r', '\n']).to_string())
+                .map_err(|e| {
+                    format!("failed to read secret from {}: {}", path.display(), e).into()
+                }),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+pub enum SecurityProtocol {
+    #[default]
+    #[serde(rename = "PLAINTEXT")]
+    Plaintext,
+    #[serde(rename = "SSL")]
+    Ssl,
+    #[serde(rename = "SASL_PLAINTEXT")]
+    SaslPlaintext,
+    #[serde(rename = "SASL_SSL")]
+    SaslSsl,
+}
+
+impl SecurityProtocol {
+    fn as_str(&self) -> &'static str {
+        match self {
+            SecurityProtocol::Plaintext => "PLAINTEXT",
+            SecurityProtocol::Ssl => "SSL",
+            SecurityProtocol::SaslPlaintext => "SASL_PLAINTEXT",
+            SecurityProtocol::SaslSsl => "SASL_SSL",
+        }
+    }
+
+    fn uses_sasl(&self) -> bool {
+        matches!(
+            self,
+            SecurityProtocol::SaslPlaintext | SecurityProtocol::SaslSsl
+        )
+    }
+
+    fn uses_ssl(&self) -> bool {
+        matches!(self, SecurityProtocol::Ssl | SecurityProtocol::SaslSsl)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum SaslMechanism {
+    #[serde(rename = "PLAIN")]
+    Plain,
+    #[serde(rename = "SCRAM-SHA-256")]
+    ScramSha256,
+    #[serde(rename = "SCRAM-SHA-512")]
+    ScramSha512,
+}
+
+impl SaslMechanism {
+    fn as_str(&self) -> &'static str {
+        match self {
+            SaslMechanism::Plain => "PLAIN",
+            SaslMechanism::ScramSha256 => "SCRAM-SHA-256",
+            SaslMechanism::ScramSha512 => "SCRAM-SHA-512",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SaslSettings {
+    pub mechanism: SaslMechanism,
+    pub username: String,
+    pub password: SecretSource,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SslSettings {
+    pub ca_location: Option<PathBuf>,
+    pub certificate_location: Option<PathBuf>,
+    pub key_location: Option<PathBuf>,
+    pub key_password: Option<SecretSource>,
+}
+
+/// Kafka client authentication and transport encryption.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct KafkaSecurity {
+    #[serde(default)]
+    pub protocol: SecurityProtocol,
+    pub sasl: Option<SaslSettings>,
+    pub ssl: Option<SslSettings>,
+}
+
+impl KafkaSecurity {
+    pub fn validate(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let protocol = self.protocol.as_str();
+        match (&self.sasl, self.protocol.uses_sasl()) {
+            (None, true) => {
+                return Err(format!("kafka_security.sasl is required for {}", protocol).into())
+            }
+            (Some(_), false) => {
+                return Err(format!("kafka_security.sasl is not used by {}", protocol).into())
+            }
+            _ => {}
+        }
+        if self.ssl.is_some() && !self.protocol.uses_ssl() {
+            return Err(format!("kafka_security.ssl is not used by {}", protocol).into());
+        }
+        if let Some(ssl) = &self.ssl {
+            if ssl.certificate_location.is_some() != ssl.key_location.is_some() {
+                return Err(
+                    "kafka_security.ssl.certificate_location and key_location must be set together"
+                        .into(),
+                );
+            }
+        }
+        // Surface missing env vars or unreadable files at startup rather than on first use.
+        self.properties().map(|_| ())
+    }
+
+    /// Resolves the librdkafka properties for this configuration, reading any secrets.
+    pub fn properties(&self) -> Result<BTreeMap<String, String>, Box<dyn std::error::Error>> {
+        let mut props = BTreeMap::new();
+        props.insert(
+            "security.protocol".to_string(),
+            self.protocol.as_str().to_string(),
+        );
+
+        if let Some(sasl) = &self.sasl {
+            props.insert(
+                "sasl.mechanism".to_string(),
+                sasl.mechanism.as_str().to_string(),
+            );
+            props.insert("sasl.username".to_string(), sasl.username.clone());
+            props.insert("sasl.password".to_string(), sasl.password.resolve()?);
+        }
+
+        if let Some(ssl) = &self.ssl {
+            let paths = [
+                ("ssl.ca.location", &ssl.ca_location),
+                ("ssl.certificate.location", &ssl.certificate_location),
+                ("ssl.key.location", &ssl.key_location),
+            ];
+            for (key, path) in paths {
+                if let Some(path) = path {
+                    props.insert(key.to_string(), path.display().to_string());
+                }
+            }
+            if let Some(password) = &ssl.key_password {
+                props.insert("ssl.key.password".to_string(), password.resolve()?);
+            }
+        }
+
+        Ok(props)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(json: &str) -> KafkaSecurity {
+        serde_json::from_str(json).expect("security settings should parse")
+    }
+
+    #[test]
+    fn defaults_to_plaintext() {
+        let security = KafkaSecurity::default();
+        security.validate().unwrap();
+        let props = security.properties().unwrap();
+        assert_eq!(props.len(), 1);
+        assert_eq!(props["security.protocol"], "PLAINTEXT");
+    }
+
+    #[test]
+    fn resolves_sasl_password_from_env() {
+        std::env::set_var("TERRARIUM_TEST_KAFKA_PASSWORD", "s3cret");
+        let security = parse(
+            r#"{
+                "protocol": "SASL_SSL",
+                "sasl": {
+                    "mechanism": "SCRAM-SHA-512",
+                    "username": "consumer",
+                    "password": { "env": "TERRARIUM_TEST_KAFKA_PASSWORD" }
+                },
+                "ssl": { "ca_location": "/etc/kafka/ca.pem" }
+            }"#,
+        );
+        security.validate().unwrap();
+
+        let props = security.properties().unwrap();
+        assert_eq!(props["security.protocol"], "SASL_SSL");
+        assert_eq!(props["sasl.mechanism"], "SCRAM-SHA-512");
+        assert_eq!(props["sasl.username"], "consumer");
+        assert_eq!(props["sasl.password"], "s3cret");
+        assert_eq!(props["ssl.ca.location"], "/etc/kafka/ca.pem");
+    }
+
+    #[test]
+    fn resolves_secret_from_file() {
+        let path =
+            std::env::temp_dir().join(format!("terrarium-kafka-secret-{}", std::process::id()));
+        std::fs::write(&path, "from-file\n").unwrap();
+        let secret = SecretSource::File(path.clone());
+        assert_eq!(secret.resolve().unwrap(), "from-file");
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn rejects_inconsistent_settings() {
+        assert!(parse(r#"{"protocol": "SASL_PLAINTEXT"}"#)
+            .validate()
+            .is_err());
+        assert!(parse(r#"{"protocol": "PLAINTEXT", "ssl": {}}"#)
+            .validate()
+            .is_err());
+        assert!(
+            parse(r#"{"protocol": "SSL", "ssl": {"certificate_location": "/tmp/cert.pem"}}"#)
+                .validate()
+                .is_err()
+        );
+        assert!(parse(r#"{"protocol": "SASL_SSL", "sasl": {"mechanism": "PLAIN", "username": "u", "password": {"env": "TERRARIUM_TEST_UNSET_PASSWORD"}}}"#)
+            .validate()
+            .is_err());
+        assert!(serde_json::from_str::<KafkaSecurity>(
+            r#"{"protocol": "SASL_SSL", "sasl": {"mechanism": "PLAIN", "username": "u", "password": "inline"}}"#
+        )
+        .is_err());
+    }
+}
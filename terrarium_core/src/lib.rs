@@ -0,0 +1,9 @@
+//! Building blocks shared by the terrarium API and consumer binaries.
+
+pub mod admin;
+pub mod config;
+pub mod database;
+pub mod kafka_security;
+pub mod logging;
+pub mod metrics;
+pub mod reload;
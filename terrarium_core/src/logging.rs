@@ -0,0 +1,95 @@
+//! `env_logger` output behind a filter that can be replaced at runtime, so a
+//! config reload can change the log level without a restart.
+
+use env_logger::filter::{Builder as FilterBuilder, Filter};
+use log::{LevelFilter, Log, Metadata, Record};
+use std::sync::{Arc, RwLock};
+
+struct ReloadableLogger {
+    inner: env_logger::Logger,
+    filter: Arc<RwLock<Filter>>,
+}
+
+impl Log for ReloadableLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.filter.read().unwrap().enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if self.filter.read().unwrap().matches(record) {
+            self.inner.log(record);
+        }
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+/// Replaces the filter installed by [`init`].
+#[derive(Clone)]
+pub struct LogHandle {
+    filter: Arc<RwLock<Filter>>,
+}
+
+impl LogHandle {
+    /// `directives` use `RUST_LOG` syntax (e.g. `info,consumer=debug`); `None`
+    /// falls back to `RUST_LOG`.
+    pub fn set_filter(&self, directives: Option<&str>) {
+        let filter = build_filter(directives);
+        log::set_max_level(filter.filter());
+        *self.filter.write().unwrap() = filter;
+    }
+}
+
+fn build_filter(directives: Option<&str>) -> Filter {
+    let mut builder = FilterBuilder::new();
+    match directives
+        .map(str::to_string)
+        .or_else(|| std::env::var("RUST_LOG").ok())
+    {
+        Some(spec) => builder.parse(&spec),
+        None => builder.filter_level(LevelFilter::Error),
+    };
+    builder.build()
+}
+
+/// Installs the global logger, filtered by `RUST_LOG` until the handle says
+/// otherwise. Replaces `env_logger::init()`.
+pub fn init() -> LogHandle {
+    let filter = build_filter(None);
+    let max_level = filter.filter();
+    let filter = Arc::new(RwLock::new(filter));
+    let logger = ReloadableLogger {
+        // Everything reaching the inner logger has already passed `filter`.
+        // `Builder::new` so `RUST_LOG` directives cannot filter it a second time.
+        inner: env_logger::Builder::new()
+            .filter_level(LevelFilter::Trace)
+            .build(),
+        filter: filter.clone(),
+    };
+    match log::set_boxed_logger(Box::new(logger)) {
+        Ok(()) => log::set_max_level(max_level),
+        Err(e) => eprintln!("logger already initialized: {}", e),
+    }
+    LogHandle { filter }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_filter_from_directives() {
+        let filter = build_filter(Some("warn,terrarium_core=debug"));
+        assert_eq!(filter.filter(), LevelFilter::Debug);
+        let metadata = |target| {
+            Metadata::builder()
+                .level(log::Level::Info)
+                .target(target)
+                .build()
+        };
+        assert!(filter.enabled(&metadata("terrarium_core::admin")));
+        assert!(!filter.enabled(&metadata("hyper")));
+    }
+}
@@ -0,0 +1,198 @@
+use std::collections::BTreeSet;
+
+use prometheus::{
+    Encoder, Histogram, HistogramOpts, IntCounter, IntCounterVec, IntGauge, IntGaugeVec, Opts,
+    Registry, TextEncoder,
+};
+
+pub fn register_int_counter(
+    registry: &Registry,
+    name: &str,
+    help: &str,
+) -> prometheus::Result<IntCounter> {
+    let counter = IntCounter::with_opts(Opts::new(name, help))?;
+    registry.register(Box::new(counter.clone()))?;
+    Ok(counter)
+}
+
+pub fn register_int_counter_vec(
+    registry: &Registry,
+    name: &str,
+    help: &str,
+    labels: &[&str],
+) -> prometheus::Result<IntCounterVec> {
+    let counter = IntCounterVec::new(Opts::new(name, help), labels)?;
+    registry.register(Box::new(counter.clone()))?;
+    Ok(counter)
+}
+
+pub fn register_int_gauge(
+    registry: &Registry,
+    name: &str,
+    help: &str,
+) -> prometheus::Result<IntGauge> {
+    let gauge = IntGauge::with_opts(Opts::new(name, help))?;
+    registry.register(Box::new(gauge.clone()))?;
+    Ok(gauge)
+}
+
+pub fn register_int_gauge_vec(
+    registry: &Registry,
+    name: &str,
+    help: &str,
+    labels: &[&str],
+) -> prometheus::Result<IntGaugeVec> {
+    let gauge = IntGaugeVec::new(Opts::new(name, help), labels)?;
+    registry.register(Box::new(gauge.clone()))?;
+    Ok(gauge)
+}
+
+pub fn register_histogram(
+    registry: &Registry,
+    name: &str,
+    help: &str,
+) -> prometheus::Result<Histogram> {
+    let histogram = Histogram::with_opts(HistogramOpts::new(name, help))?;
+    registry.register(Box::new(histogram.clone()))?;
+    Ok(histogram)
+}
+
+/// Renders every metric in `registry` in the Prometheus text format, returning
+/// the body along with its content type.
+pub fn encode_text(registry: &Registry) -> prometheus::Result<(Vec<u8>, String)> {
+    let encoder = TextEncoder::new();
+    let mut buffer = Vec::new();
+    encoder.encode(&registry.gather(), &mut buffer)?;
+    Ok((buffer, encoder.format_type().to_string()))
+}
+
+/// Names of all metric families currently registered.
+pub fn registered_names(registry: &Registry) -> Vec<String> {
+    registry
+        .gather()
+        .iter()
+        .map(|family| family.get_name().to_string())
+        .collect()
+}
+
+/// Whether a metric called `name` is registered. Unlike [`registered_names`],
+/// this also sees labelled metrics that have no series yet. Meant for tests:
+/// the registry remembers the probe's help text, so the name can't later be
+/// registered with a different one.
+pub fn is_registered(registry: &Registry, name: &str) -> bool {
+    let Ok(probe) = IntGauge::new(name, "probe") else {
+        return false;
+    };
+    match registry.register(Box::new(probe.clone())) {
+        Ok(()) => {
+            let _ = registry.unregister(Box::new(probe));
+            false
+        }
+        Err(_) => true,
+    }
+}
+
+/// Metric names starting with `prefix` that appear in `text`, such as a
+/// Grafana dashboard or a Prometheus rules file. Histogram series suffixes
+/// (`_bucket`, `_sum`, `_count`) are stripped.
+pub fn referenced_names(text: &str, prefix: &str) -> BTreeSet<String> {
+    text.split(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == ':'))
+        .filter(|word| word.starts_with(prefix))
+        .map(|word| {
+            ["_bucket", "_sum", "_count"]
+                .iter()
+                .find_map(|suffix| word.strip_suffix(suffix))
+                .unwrap_or(word)
+                .to_string()
+        })
+        .collect()
+}
+
+/// The provisioned Grafana dashboards and Prometheus rules in `local/`.
+const MONITORING: &[(&str, &str)] = &[
+    ("alerts.yml", include_str!("../../local/alerts.yml")),
+    (
+        "grafana/dashboards/api.json",
+        include_str!("../../local/grafana/dashboards/api.json"),
+    ),
+    (
+        "grafana/dashboards/consumer.json",
+        include_str!("../../local/grafana/dashboards/consumer.json"),
+    ),
+];
+
+/// Panics unless every metric starting with `prefix` that the dashboards and
+/// alert rules in `local/` use is registered in `registry`, or if a dashboard
+/// is not valid JSON. Meant for the services' tests.
+pub fn assert_referenced_registered(registry: &Registry, prefix: &str) {
+    let mut referenced = 0;
+    for (path, source) in MONITORING {
+        if path.ends_with(".json") {
+            if let Err(e) = serde_json::from_str::<serde_json::Value>(source) {
+                panic!("local/{} is not valid JSON: {}", path, e);
+            }
+        }
+        for name in referenced_names(source, prefix) {
+            assert!(
+                is_registered(registry, &name),
+                "local/{} uses {}, which is not registered",
+                path,
+                name
+            );
+            referenced += 1;
+        }
+    }
+    assert!(referenced > 0, "local/ uses no {}* metrics", prefix);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn registers_and_encodes_metrics() {
+        let registry = Registry::new();
+        let counter = register_int_counter(&registry, "test_events_total", "Events").unwrap();
+        register_histogram(&registry, "test_latency_seconds", "Latency").unwrap();
+        counter.inc();
+
+        let (body, content_type) = encode_text(&registry).unwrap();
+        let body = String::from_utf8(body).unwrap();
+        assert!(content_type.starts_with("text/plain"));
+        assert!(body.contains("test_events_total 1"));
+
+        let names = registered_names(&registry);
+        assert!(names.contains(&"test_events_total".to_string()));
+        assert!(names.contains(&"test_latency_seconds".to_string()));
+    }
+
+    #[test]
+    fn rejects_duplicate_registration() {
+        let registry = Registry::new();
+        register_int_gauge(&registry, "test_gauge", "Gauge").unwrap();
+        assert!(register_int_gauge(&registry, "test_gauge", "Gauge").is_err());
+    }
+
+    #[test]
+    fn finds_referenced_metrics_and_checks_registration() {
+        let registry = Registry::new();
+        register_int_counter_vec(&registry, "test_writes_total", "Writes", &["sink"]).unwrap();
+        register_histogram(&registry, "test_latency_seconds", "Latency").unwrap();
+
+        let expr = r#"sum by (sink) (rate(test_writes_total{sink="db"}[5m])) /
+            histogram_quantile(0.99, rate(test_latency_seconds_bucket[5m])) + other_total"#;
+        let names = referenced_names(expr, "test_");
+        assert_eq!(
+            names.into_iter().collect::<Vec<_>>(),
+            ["test_latency_seconds", "test_writes_total"]
+        );
+
+        // A labelled metric without series is invisible to `registered_names`.
+        assert!(!registered_names(&registry).contains(&"test_writes_total".to_string()));
+        assert!(is_registered(&registry, "test_writes_total"));
+        assert!(is_registered(&registry, "test_latency_seconds"));
+        assert!(!is_registered(&registry, "test_missing_total"));
+        // The probe is unregistered again, so asking twice gives the same answer.
+        assert!(!is_registered(&registry, "test_missing_total"));
+    }
+}
@@ -0,0 +1,252 @@
+//! Reloading a binary's config file without a restart. Each binary decides
+//! which settings it can apply live; every other change is reported as
+//! requiring a restart.
+
+use crate::admin::{self, AdminServer};
+use hyper::Method;
+use serde::Serialize;
+use serde_json::Value;
+use std::sync::{Arc, Mutex, RwLock};
+
+/// Outcome of a reload, as dotted config paths (e.g. `outbox.batch_size`).
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct ReloadReport {
+    /// Live settings changed since the previous reload; now in effect.
+    pub applied: Vec<String>,
+    /// Settings that differ from the running process's startup config and
+    /// only take effect after a restart.
+    pub restart_required: Vec<String>,
+}
+
+impl ReloadReport {
+    pub fn log(&self) {
+        log::info!("Config reloaded; applied: {:?}", self.applied);
+        if !self.restart_required.is_empty() {
+            log::warn!(
+                "Config changes that need a restart: {:?}",
+                self.restart_required
+            );
+        }
+    }
+}
+
+/// Paths of every leaf that differs between two JSON documents. Objects are
+/// compared key by key; anything else, including arrays, as a whole.
+pub fn changed_paths(old: &Value, new: &Value) -> Vec<String> {
+    let mut changed = Vec::new();
+    collect_changes("", old, new, &mut changed);
+    changed
+}
+
+fn collect_changes(prefix: &str, old: &Value, new: &Value, changed: &mut Vec<String>) {
+    if let (Value::Object(old), Value::Object(new)) = (old, new) {
+        let mut keys: Vec<&String> = old.keys().chain(new.keys()).collect();
+        keys.sort();
+        keys.dedup();
+        for key in keys {
+            let path = if prefix.is_empty() {
+                key.clone()
+            } else {
+                format!("{}.{}", prefix, key)
+            };
+            let missing = Value::Null;
+            collect_changes(
+                &path,
+                old.get(key).unwrap_or(&missing),
+                new.get(key).unwrap_or(&missing),
+                changed,
+            );
+        }
+    } else if old != new {
+        changed.push(prefix.to_string());
+    }
+}
+
+/// Remembers the config a process started with and the one it last applied.
+pub struct ConfigTracker {
+    live: &'static [&'static str],
+    startup: Value,
+    applied: Mutex<Value>,
+}
+
+impl ConfigTracker {
+    /// `live` lists the paths (and path prefixes) applied without a restart.
+    pub fn new(config_str: &str, live: &'static [&'static str]) -> serde_json::Result<Self> {
+        let startup: Value = serde_json::from_str(config_str)?;
+        Ok(Self {
+            live,
+            applied: Mutex::new(startup.clone()),
+            startup,
+        })
+    }
+
+    fn is_live(&self, path: &str) -> bool {
+        self.live.iter().any(|live| {
+            path == *live
+                || path
+                    .strip_prefix(live)
+                    .is_some_and(|rest| rest.starts_with('.'))
+        })
+    }
+
+    /// Records an already-validated config as applied and reports what changed.
+    pub fn update(&self, config_str: &str) -> serde_json::Result<ReloadReport> {
+        let new: Value = serde_json::from_str(config_str)?;
+        let mut applied = self.applied.lock().unwrap();
+        let report = ReloadReport {
+            applied: changed_paths(&applied, &new)
+                .into_iter()
+                .filter(|path| self.is_live(path))
+                .collect(),
+            restart_required: changed_paths(&self.startup, &new)
+                .into_iter()
+                .filter(|path| !self.is_live(path))
+                .collect(),
+        };
+        *applied = new;
+        Ok(report)
+    }
+}
+
+/// A setting that a reload can replace while readers hold on to it.
+pub struct Live<T> {
+    value: RwLock<Arc<T>>,
+}
+
+impl<T> Live<T> {
+    pub fn new(value: T) -> Arc<Self> {
+        Arc::new(Self {
+            value: RwLock::new(Arc::new(value)),
+        })
+    }
+
+    pub fn get(&self) -> Arc<T> {
+        self.value.read().unwrap().clone()
+    }
+
+    pub fn set(&self, value: T) {
+        *self.value.write().unwrap() = Arc::new(value);
+    }
+}
+
+/// Re-reads the config, applies live settings and reports the rest.
+pub type ReloadFn = Arc<dyn Fn() -> Result<ReloadReport, String> + Send + Sync>;
+
+/// Reloads on every SIGHUP until the process exits.
+pub async fn reload_on_hangup(reload: ReloadFn) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangups = match signal(SignalKind::hangup()) {
+        Ok(hangups) => hangups,
+        Err(e) => {
+            log::error!("Failed to listen for SIGHUP: {}", e);
+            return;
+        }
+    };
+    while hangups.recv().await.is_some() {
+        log::info!("Received SIGHUP; reloading config");
+        match reload() {
+            Ok(report) => report.log(),
+            Err(e) => log::error!("Config reload failed; keeping current config: {}", e),
+        }
+    }
+}
+
+/// Registers `POST /admin/reload`, which requires `token` and returns the
+/// [`ReloadReport`] as JSON.
+pub fn route(server: AdminServer, token: String, reload: ReloadFn) -> AdminServer {
+    server.route(Method::POST, "/admin/reload", move |req| {
+        let authorized = admin::bearer_authorized(&req, &token);
+        let reload = reload.clone();
+        async move {
+            if !authorized {
+                return admin::text(401, "unauthorized");
+            }
+            match reload() {
+                Ok(report) => {
+                    report.log();
+                    admin::json(200, &report)
+                }
+                Err(e) => {
+                    log::error!("Config reload failed; keeping current config: {}", e);
+                    admin::json(400, &serde_json::json!({ "error": e }))
+                }
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::{body::to_bytes, Body, Request as HttpRequest};
+    use prometheus::Registry;
+    use serde_json::json;
+
+    const LIVE: &[&str] = &["log_level", "outbox.batch_size", "rate_limit"];
+
+    #[test]
+    fn lists_changed_leaves() {
+        let old = json!({"a": 1, "b": {"c": [1, 2], "d": "x"}, "gone": true});
+        let new = json!({"a": 1, "b": {"c": [1, 3], "d": "x"}, "added": {"e": 1}});
+        assert_eq!(changed_paths(&old, &new), vec!["added", "b.c", "gone"]);
+        assert!(changed_paths(&old, &old).is_empty());
+    }
+
+    #[test]
+    fn classifies_live_and_restart_changes() {
+        let tracker = ConfigTracker::new(
+            r#"{"log_level": "info", "topic": "a", "outbox": {"batch_size": 10}}"#,
+            LIVE,
+        )
+        .unwrap();
+
+        let report = tracker
+            .update(r#"{"log_level": "debug", "topic": "b", "outbox": {"batch_size": 10}, "rate_limit_x": 1}"#)
+            .unwrap();
+        assert_eq!(report.applied, vec!["log_level"]);
+        assert_eq!(report.restart_required, vec!["rate_limit_x", "topic"]);
+
+        // Still differs from startup, but was already applied last time.
+        let report = tracker
+            .update(r#"{"log_level": "debug", "topic": "b", "outbox": {"batch_size": 20}}"#)
+            .unwrap();
+        assert_eq!(report.applied, vec!["outbox.batch_size"]);
+        assert_eq!(report.restart_required, vec!["topic"]);
+    }
+
+    #[tokio::test]
+    async fn reload_route_requires_token() {
+        let reload: ReloadFn = Arc::new(|| {
+            Ok(ReloadReport {
+                applied: vec!["log_level".to_string()],
+                restart_required: vec![],
+            })
+        });
+        let server = route(
+            AdminServer::new("Test", Registry::new()),
+            "s3cret".to_string(),
+            reload,
+        );
+        let request = |token: &str| {
+            HttpRequest::builder()
+                .method(Method::POST)
+                .uri("/admin/reload")
+                .header("Authorization", format!("Bearer {}", token))
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let resp = server.handle(request("wrong")).await.unwrap();
+        assert_eq!(resp.status(), 401);
+
+        let resp = server.handle(request("s3cret")).await.unwrap();
+        assert_eq!(resp.status(), 200);
+        let body: Value =
+            serde_json::from_slice(&to_bytes(resp.into_body()).await.unwrap()).unwrap();
+        assert_eq!(
+            body,
+            json!({"applied": ["log_level"], "restart_required": []})
+        );
+    }
+}